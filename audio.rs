@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+/// Playing time of an MPEG audio layer III stream, found by walking its frame headers.
+/// An ID3v2 tag at the start is skipped, and anything after the last whole frame (such as
+/// an ID3v1 tag) is ignored. Returns `None` if no frames are found.
+pub fn mp3_duration(bytes: &[u8]) -> Option<Duration> {
+    let mut pos = id3v2_len(bytes);
+    let mut seconds = 0.0;
+    let mut frames = 0;
+    while pos + 4 <= bytes.len() {
+        match frame_header(&bytes[pos..pos + 4]) {
+            Some(frame) if frame.len > 0 => {
+                seconds += frame.samples as f64 / frame.sample_rate as f64;
+                frames += 1;
+                pos += frame.len;
+            }
+            // Resynchronise past junk between frames.
+            _ => pos += 1,
+        }
+    }
+    (frames > 0).then(|| Duration::from_secs_f64(seconds))
+}
+
+struct Frame {
+    len: usize,
+    samples: u32,
+    sample_rate: u32,
+}
+
+fn frame_header(header: &[u8]) -> Option<Frame> {
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    // 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5; 1 is reserved.
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    if version == 1 || layer != 1 {
+        return None;
+    }
+    let bitrate_index = (header[2] >> 4) as usize;
+    let rate_index = ((header[2] >> 2) & 0x03) as usize;
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+    const MPEG1_KBPS: [u32; 16] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0];
+    const MPEG2_KBPS: [u32; 16] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0];
+    const MPEG1_RATES: [u32; 4] = [44_100, 48_000, 32_000, 0];
+    let (kbps, sample_rate, samples) = match version {
+        3 => (MPEG1_KBPS[bitrate_index], MPEG1_RATES[rate_index], 1152),
+        2 => (MPEG2_KBPS[bitrate_index], MPEG1_RATES[rate_index] / 2, 576),
+        _ => (MPEG2_KBPS[bitrate_index], MPEG1_RATES[rate_index] / 4, 576),
+    };
+    if kbps == 0 || sample_rate == 0 {
+        return None;
+    }
+    let len = (samples / 8 * kbps * 1000 / sample_rate) as usize + padding;
+    Some(Frame { len, samples, sample_rate })
+}
+
+/// Length of a leading ID3v2 tag, or 0 if there is none.
+fn id3v2_len(bytes: &[u8]) -> usize {
+    if bytes.len() < 10 || &bytes[..3] != b"ID3" {
+        return 0;
+    }
+    // Syncsafe: 7 bits per byte.
+    let size = bytes[6..10].iter().fold(0usize, |size, b| (size << 7) | (*b & 0x7F) as usize);
+    let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+    10 + size + footer
+}
@@ -0,0 +1,103 @@
+use crate::{digest, extract_file_info, ledger::Ledger, should_process_file};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use tracing::error;
+use walkdir::WalkDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillOrder {
+    OldestFirst,
+    NewestFirst,
+}
+
+impl FromStr for BackfillOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest_first" => Ok(BackfillOrder::OldestFirst),
+            "newest_first" => Ok(BackfillOrder::NewestFirst),
+            _ => Err(format!("expected oldest_first or newest_first, got {:?}", s)),
+        }
+    }
+}
+
+/// A complete mp3/txt pair found on disk that the ledger has no record of.
+#[derive(Debug)]
+pub struct Candidate {
+    pub mp3_path: PathBuf,
+    pub txt_path: PathBuf,
+    /// Later of the two files' modification times.
+    pub modified: SystemTime,
+}
+
+/// Walks `roots` for pairs the watcher would have picked up, skipping anything older than
+/// `max_age` and anything the ledger shows was already uploaded with the same content to
+/// every one of `sinks`.
+pub fn scan(
+    roots: &[PathBuf],
+    ledger: &Ledger,
+    sinks: &[&str],
+    max_age: Option<Duration>,
+    order: BackfillOrder,
+) -> Vec<Candidate> {
+    let now = SystemTime::now();
+    let mut candidates = Vec::new();
+    for root in roots {
+        for entry in WalkDir::new(root).into_iter().filter_map(|entry| entry.ok()) {
+            let path = entry.into_path();
+            if path.extension().is_none_or(|ext| ext != "mp3") || !should_process_file(&path, roots) {
+                continue;
+            }
+            let Some((mp3_path, txt_path)) = extract_file_info(&path) else {
+                continue;
+            };
+            let Some(modified) = [&mp3_path, &txt_path]
+                .iter()
+                .filter_map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+                .max()
+            else {
+                continue;
+            };
+            if max_age.is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age) {
+                continue;
+            }
+            match already_uploaded(ledger, sinks, &mp3_path, &txt_path) {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => error!(mp3 = %mp3_path.display(), "Failed to check upload ledger: {}", e),
+            }
+            candidates.push(Candidate { mp3_path, txt_path, modified });
+        }
+    }
+    match order {
+        BackfillOrder::OldestFirst => candidates.sort_by_key(|c| c.modified),
+        BackfillOrder::NewestFirst => candidates.sort_by_key(|c| std::cmp::Reverse(c.modified)),
+    }
+    candidates
+}
+
+/// Only hashes the files when the ledger already knows the stem, so large archives of
+/// never-uploaded calls are scanned without reading every mp3.
+fn already_uploaded(ledger: &Ledger, sinks: &[&str], mp3_path: &PathBuf, txt_path: &PathBuf) -> rusqlite::Result<bool> {
+    let stem = mp3_path.file_stem().unwrap_or_default().to_string_lossy();
+    if !ledger.has_stem(&stem)? {
+        return Ok(false);
+    }
+    let (Ok(mp3), Ok(txt)) = (fs::read(mp3_path), fs::read(txt_path)) else {
+        return Ok(false);
+    };
+    let (mp3_sha256, txt_sha256) = (digest::sha256_hex(&mp3), digest::sha256_hex(&txt));
+    for sink in sinks {
+        if ledger.find(sink, &stem, &mp3_sha256, &txt_sha256)?.is_none() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
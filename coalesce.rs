@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Collapses the create/modify/rename bursts that one call produces on both its mp3 and
+/// txt into a single pairing decision, keyed on the file path without its extension.
+#[derive(Debug)]
+pub struct Coalescer {
+    /// How long a stem must go without events before it is handed on.
+    debounce: Duration,
+    /// How long events for an already queued stem keep being ignored.
+    remember: Duration,
+    pending: HashMap<PathBuf, Instant>,
+    finished: HashMap<PathBuf, Instant>,
+}
+
+impl Coalescer {
+    pub fn new(debounce: Duration, remember: Duration) -> Self {
+        Coalescer { debounce, remember, pending: HashMap::new(), finished: HashMap::new() }
+    }
+
+    /// Notes an event for `path`. Returns `false` if its call has already been queued.
+    pub fn observe(&mut self, path: &Path) -> bool {
+        let Some(stem) = stem_of(path) else {
+            return false;
+        };
+        if self.finished.contains_key(&stem) {
+            return false;
+        }
+        self.pending.insert(stem, Instant::now());
+        true
+    }
+
+    /// Stems that have been quiet for the debounce window, each returned once per burst.
+    pub fn due(&mut self) -> Vec<PathBuf> {
+        let now = Instant::now();
+        let remember = self.remember;
+        self.finished.retain(|_, at| now.duration_since(*at) < remember);
+
+        let debounce = self.debounce;
+        let mut due = Vec::new();
+        self.pending.retain(|stem, last_event| {
+            if now.duration_since(*last_event) >= debounce {
+                due.push(stem.clone());
+                false
+            } else {
+                true
+            }
+        });
+        due
+    }
+
+    /// How many calls have had events but aren't yet due.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Marks the call at `path` as queued so later events for it are ignored.
+    pub fn finish(&mut self, path: &Path) {
+        if let Some(stem) = stem_of(path) {
+            self.pending.remove(&stem);
+            self.finished.insert(stem, Instant::now());
+        }
+    }
+}
+
+/// `dir/20240101_120000..__TO_1.mp3` -> `dir/20240101_120000..__TO_1`
+pub fn stem_of(path: &Path) -> Option<PathBuf> {
+    Some(path.parent()?.join(path.file_stem()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    const DEBOUNCE: Duration = Duration::from_millis(50);
+
+    fn paths(stem: &str) -> (PathBuf, PathBuf) {
+        (PathBuf::from(format!("/rec/{}.mp3", stem)), PathBuf::from(format!("/rec/{}.txt", stem)))
+    }
+
+    #[test]
+    fn events_on_both_files_become_one_decision_after_the_debounce() {
+        let mut coalescer = Coalescer::new(DEBOUNCE, Duration::from_secs(60));
+        let (mp3, txt) = paths("a");
+        assert!(coalescer.observe(&mp3));
+        assert!(coalescer.observe(&txt));
+        assert!(coalescer.observe(&mp3));
+        assert_eq!(coalescer.pending(), 1);
+        assert!(coalescer.due().is_empty());
+
+        sleep(DEBOUNCE * 2);
+        assert_eq!(coalescer.due(), [PathBuf::from("/rec/a")]);
+        assert!(coalescer.due().is_empty());
+        assert_eq!(coalescer.pending(), 0);
+    }
+
+    #[test]
+    fn each_event_restarts_the_debounce() {
+        let debounce = Duration::from_millis(200);
+        let mut coalescer = Coalescer::new(debounce, Duration::from_secs(60));
+        let (mp3, txt) = paths("a");
+        coalescer.observe(&mp3);
+        sleep(debounce / 2);
+        coalescer.observe(&txt);
+        sleep(debounce / 2);
+        assert!(coalescer.due().is_empty());
+        sleep(debounce);
+        assert_eq!(coalescer.due().len(), 1);
+    }
+
+    #[test]
+    fn finished_calls_are_ignored_for_the_dedupe_window() {
+        let mut coalescer = Coalescer::new(Duration::ZERO, DEBOUNCE);
+        let (mp3, txt) = paths("a");
+        coalescer.observe(&mp3);
+        coalescer.finish(&mp3);
+        assert_eq!(coalescer.pending(), 0);
+        assert!(!coalescer.observe(&txt));
+        assert!(coalescer.due().is_empty());
+
+        // Another call in the same directory isn't affected.
+        assert!(coalescer.observe(&paths("b").0));
+
+        sleep(DEBOUNCE * 2);
+        coalescer.due();
+        assert!(coalescer.observe(&mp3));
+        assert_eq!(coalescer.due().len(), 1);
+    }
+
+    #[test]
+    fn stem_drops_only_the_extension() {
+        let stem = stem_of(Path::new("/rec/tg/20240101_120000__TO_1.mp3"));
+        assert_eq!(stem, Some(PathBuf::from("/rec/tg/20240101_120000__TO_1")));
+        assert_eq!(stem_of(Path::new("/")), None);
+    }
+}
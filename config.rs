@@ -0,0 +1,1257 @@
+use crate::{
+    backfill::BackfillOrder,
+    filename::{CallRecord, FilenamePattern},
+    logging::{self, LogFormat},
+    retry::RetryPolicy,
+    template::{EventTemplate, Template, EVENT_FIELDS, FIELDS},
+    timestamp::{SourceTimezone, TimestampFormat},
+    tls,
+};
+use clap::Args;
+use reqwest::Url;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Loaded when no `--config` / `UPLOADER_CONFIG` is given and the file exists.
+pub const DEFAULT_CONFIG_PATH: &str = "uploader.toml";
+
+/// Name of the sink built from `[upload]` when no `[[sinks]]` are configured. Queue and
+/// ledger rows from before sinks existed are assigned to it.
+pub const DEFAULT_SINK: &str = "upload";
+
+/// Uploader settings. Built from defaults, then the TOML file, then environment
+/// variables, then command-line flags, each layer overriding the one before.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub watch: WatchConfig,
+    pub upload: UploadConfig,
+    pub queue: QueueConfig,
+    pub ledger: LedgerConfig,
+    pub backfill: BackfillConfig,
+    pub retry: RetryConfig,
+    pub tls: TlsConfig,
+    pub timestamp: TimestampConfig,
+    pub shutdown: ShutdownConfig,
+    pub log: LogConfig,
+    pub http: HttpConfig,
+    pub mqtt: MqttConfig,
+    /// Services sent a JSON POST about each call once a sink is done with it.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Destinations every call is sent to; a single `DEFAULT_SINK` using `[upload]` if empty.
+    pub sinks: Vec<SinkConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Roots that use the `[upload]` settings unchanged. Shorthand for `[[watch.roots]]`
+    /// entries with only a `path`.
+    pub directories: Vec<PathBuf>,
+    pub roots: Vec<RootConfig>,
+    /// Quiet period after the last event for a call before its files are paired.
+    pub debounce_secs: f64,
+    /// How long further events for an already queued call are ignored.
+    pub dedupe_window_secs: f64,
+    /// How long an mp3 or txt must keep the same size and mtime before it is uploaded.
+    pub settle_secs: f64,
+    /// Treat a close-after-write event as "finished" without waiting out `settle_secs`,
+    /// on platforms that report one (Linux inotify).
+    pub close_write: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            directories: Vec::new(),
+            roots: Vec::new(),
+            debounce_secs: 1.0,
+            dedupe_window_secs: 3600.0,
+            settle_secs: 2.0,
+            close_write: true,
+        }
+    }
+}
+
+impl WatchConfig {
+    pub fn root_paths(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| root.path.clone()).collect()
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs_f64(self.debounce_secs)
+    }
+
+    pub fn dedupe_window(&self) -> Duration {
+        Duration::from_secs_f64(self.dedupe_window_secs)
+    }
+
+    pub fn settle(&self) -> Duration {
+        Duration::from_secs_f64(self.settle_secs)
+    }
+}
+
+/// A watched recording tree, e.g. one SDRTrunk instance. Anything left unset falls back to
+/// the `[upload]` settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RootConfig {
+    pub path: PathBuf,
+    /// Sent in the `fields.system` form field for every call from this root.
+    pub system: Option<String>,
+    /// Regex for filenames in this root, in place of SDRTrunk's naming scheme.
+    pub filename_pattern: Option<FilenamePattern>,
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+}
+
+impl RootConfig {
+    /// Parses a recording filename with this root's pattern.
+    pub fn parse_filename(&self, filename: &str) -> Option<CallRecord> {
+        self.filename_pattern.as_ref().unwrap_or(FilenamePattern::sdrtrunk()).parse(filename)
+    }
+
+    pub fn endpoint<'a>(&'a self, upload: &'a UploadConfig) -> &'a str {
+        self.endpoint.as_deref().unwrap_or(&upload.endpoint)
+    }
+
+    pub fn api_key<'a>(&'a self, upload: &'a UploadConfig) -> Option<&'a str> {
+        self.api_key.as_deref().or(upload.api_key.as_deref())
+    }
+
+    pub fn api_key_header<'a>(&'a self, upload: &'a UploadConfig) -> &'a str {
+        self.api_key_header.as_deref().unwrap_or(&upload.api_key_header)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub api_key_header: String,
+    pub timeout_secs: f64,
+    pub connect_timeout_secs: f64,
+    /// Number of uploads that may be in flight at once.
+    pub workers: usize,
+    /// Keys checked, in order, for a server-assigned call id in a JSON acknowledgement.
+    pub ack_id_fields: Vec<String>,
+    /// What to send as the radio id when the filename has no `FROM` segment.
+    pub unknown_radio_id: UnknownRadioId,
+    pub fields: FormFields,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            endpoint: String::new(),
+            api_key: None,
+            api_key_header: "X-API-Key".to_string(),
+            timeout_secs: 120.0,
+            connect_timeout_secs: 10.0,
+            workers: 1,
+            ack_id_fields: vec!["callId".to_string(), "call_id".to_string(), "id".to_string()],
+            unknown_radio_id: UnknownRadioId::Omit,
+            fields: FormFields::default(),
+        }
+    }
+}
+
+impl UploadConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.timeout_secs)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.connect_timeout_secs)
+    }
+}
+
+/// How a call with no known source radio is reported. In TOML this is `"omit"`,
+/// `"empty"` or `{ sentinel = "..." }`; on the command line `omit`, `empty` or `sentinel:...`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownRadioId {
+    /// Leave the radio id field out of the upload.
+    Omit,
+    /// Send the radio id field with an empty value.
+    Empty,
+    /// Send this value in place of the radio id.
+    Sentinel(String),
+}
+
+impl UnknownRadioId {
+    /// The radio id to send for a call with no `FROM`, or `None` to leave the field out.
+    pub fn value(&self) -> Option<String> {
+        match self {
+            UnknownRadioId::Omit => None,
+            UnknownRadioId::Empty => Some(String::new()),
+            UnknownRadioId::Sentinel(value) => Some(value.clone()),
+        }
+    }
+}
+
+impl FromStr for UnknownRadioId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "omit" => Ok(UnknownRadioId::Omit),
+            "empty" => Ok(UnknownRadioId::Empty),
+            _ => s
+                .strip_prefix("sentinel:")
+                .map(|value| UnknownRadioId::Sentinel(value.to_string()))
+                .ok_or_else(|| format!("expected omit, empty or sentinel:<value>, got {:?}", s)),
+        }
+    }
+}
+
+/// Multipart field names sent to the upload endpoint.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormFields {
+    pub talkgroup_id: String,
+    pub timestamp: String,
+    pub radio_id: String,
+    pub mp3: String,
+    pub transcription: String,
+    /// Only sent for roots that set a `system` label.
+    pub system: String,
+}
+
+impl Default for FormFields {
+    fn default() -> Self {
+        FormFields {
+            talkgroup_id: "talkgroupId".to_string(),
+            timestamp: "timestamp".to_string(),
+            radio_id: "radioId".to_string(),
+            mp3: "mp3".to_string(),
+            transcription: "transcription".to_string(),
+            system: "system".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub path: PathBuf,
+    pub dead_letter_directory: PathBuf,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            path: PathBuf::from("upload_queue.sqlite3"),
+            dead_letter_directory: PathBuf::from("dead_letter"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedgerConfig {
+    /// SQLite file recording every call the server has accepted.
+    pub path: PathBuf,
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        LedgerConfig { path: PathBuf::from("upload_ledger.sqlite3") }
+    }
+}
+
+/// Scanning the watched directories for pairs that were never uploaded, e.g. because they
+/// were written while the uploader was down.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackfillConfig {
+    pub on_startup: bool,
+    /// Ignore pairs last modified longer ago than this.
+    pub max_age_hours: Option<f64>,
+    pub order: BackfillOrder,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        BackfillConfig { on_startup: true, max_age_hours: None, order: BackfillOrder::OldestFirst }
+    }
+}
+
+impl BackfillConfig {
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age_hours.map(|hours| Duration::from_secs_f64(hours * 3600.0))
+    }
+}
+
+/// A destination for calls. Each sink has its own queue entries, retry schedule and ledger
+/// records, so one failing destination doesn't hold up or repeat uploads to the others.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SinkConfig {
+    pub name: String,
+    /// Replaces `[retry]` for this sink.
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+    #[serde(flatten)]
+    pub kind: SinkKind,
+}
+
+impl SinkConfig {
+    pub fn retry_policy(&self, default: &RetryConfig) -> RetryPolicy {
+        self.retry.as_ref().unwrap_or(default).policy()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkKind {
+    /// The multipart POST described by `[upload]`.
+    Multipart(MultipartSinkConfig),
+    /// Rdio Scanner's `/api/call-upload`.
+    RdioScanner(RdioScannerSinkConfig),
+    /// OpenMHz's per-system upload endpoint.
+    #[serde(rename = "openmhz")]
+    OpenMhz(OpenMhzSinkConfig),
+    /// Broadcastify Calls: request an upload slot, then PUT the audio to it.
+    Broadcastify(BroadcastifySinkConfig),
+    /// An S3-compatible bucket, e.g. AWS S3 or MinIO.
+    S3(S3SinkConfig),
+    /// A local SQLite database of calls, searchable without any network.
+    Sqlite(SqliteSinkConfig),
+}
+
+/// Settings that send a multipart sink somewhere other than `[upload]` and the watch roots say.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MultipartSinkConfig {
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    pub api_key_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RdioScannerSinkConfig {
+    /// Base URL of the Rdio Scanner server; calls are posted to `/api/call-upload` under it.
+    pub url: String,
+    /// An API key from Rdio Scanner's admin page, allowed for the systems and talkgroups sent.
+    pub api_key: String,
+    /// Rdio Scanner system id for calls from roots without an entry in `systems`.
+    pub system: Option<u32>,
+    /// Rdio Scanner system ids keyed by watch root `system` label.
+    pub systems: HashMap<String, u32>,
+}
+
+impl RdioScannerSinkConfig {
+    /// The Rdio Scanner system id for calls from `root`.
+    pub fn system_id(&self, root: &RootConfig) -> Option<u32> {
+        system_for(root, self.system.as_ref(), &self.systems).copied()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenMhzSinkConfig {
+    /// Base URL of the OpenMHz API; calls are posted to `/<short name>/upload` under it.
+    pub url: String,
+    /// The upload key OpenMHz issued for the systems sent.
+    pub api_key: String,
+    /// OpenMHz system short name for calls from roots without an entry in `systems`.
+    pub system: Option<String>,
+    /// OpenMHz system short names keyed by watch root `system` label.
+    pub systems: HashMap<String, String>,
+}
+
+impl Default for OpenMhzSinkConfig {
+    fn default() -> Self {
+        OpenMhzSinkConfig {
+            url: "https://api.openmhz.com".to_string(),
+            api_key: String::new(),
+            system: None,
+            systems: HashMap::new(),
+        }
+    }
+}
+
+impl OpenMhzSinkConfig {
+    /// The OpenMHz short name for calls from `root`.
+    pub fn short_name(&self, root: &RootConfig) -> Option<&str> {
+        system_for(root, self.system.as_ref(), &self.systems).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BroadcastifySinkConfig {
+    /// Where upload slots are requested.
+    pub url: String,
+    /// The Calls API key from the Broadcastify feed provider page.
+    pub api_key: String,
+    /// Broadcastify Calls system id for calls from roots without an entry in `systems`.
+    pub system: Option<u32>,
+    /// Broadcastify Calls system ids keyed by watch root `system` label.
+    pub systems: HashMap<String, u32>,
+}
+
+impl Default for BroadcastifySinkConfig {
+    fn default() -> Self {
+        BroadcastifySinkConfig {
+            url: "https://api.broadcastify.com/call-upload".to_string(),
+            api_key: String::new(),
+            system: None,
+            systems: HashMap::new(),
+        }
+    }
+}
+
+impl BroadcastifySinkConfig {
+    /// The Broadcastify Calls system id for calls from `root`.
+    pub fn system_id(&self, root: &RootConfig) -> Option<u32> {
+        system_for(root, self.system.as_ref(), &self.systems).copied()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3SinkConfig {
+    /// Service URL, e.g. `https://s3.us-east-1.amazonaws.com`, or `http://127.0.0.1:9000` for a
+    /// local MinIO. Requests are path-style: `<endpoint>/<bucket>/<key>`.
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Falls back to the `AWS_ACCESS_KEY_ID` environment variable.
+    pub access_key_id: Option<String>,
+    /// Falls back to the `AWS_SECRET_ACCESS_KEY` environment variable.
+    pub secret_access_key: Option<String>,
+    /// Object key for the mp3. Empty path segments, from fields a call doesn't have, are dropped.
+    pub key: Template,
+    /// Object key for the transcript.
+    pub transcript_key: Template,
+}
+
+impl Default for S3SinkConfig {
+    fn default() -> Self {
+        S3SinkConfig {
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".to_string(),
+            access_key_id: None,
+            secret_access_key: None,
+            key: "{system}/{talkgroup}/{yyyy}/{mm}/{dd}/{stem}.mp3".parse().unwrap(),
+            transcript_key: "{system}/{talkgroup}/{yyyy}/{mm}/{dd}/{stem}.txt".parse().unwrap(),
+        }
+    }
+}
+
+impl S3SinkConfig {
+    /// Access key id and secret, from the config or else the standard AWS variables.
+    pub fn credentials(&self) -> Option<(String, String)> {
+        let id = self.access_key_id.clone().or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok())?;
+        let secret = self.secret_access_key.clone().or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok())?;
+        Some((id, secret))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteSinkConfig {
+    pub path: PathBuf,
+    /// Keep the mp3 itself in the database, not just its path.
+    pub store_audio: bool,
+}
+
+impl Default for SqliteSinkConfig {
+    fn default() -> Self {
+        SqliteSinkConfig { path: PathBuf::from("call_archive.sqlite3"), store_audio: false }
+    }
+}
+
+/// The entry in `systems` for `root`'s system label, or `system` if there is none.
+fn system_for<'a, T>(root: &RootConfig, system: Option<&'a T>, systems: &'a HashMap<String, T>) -> Option<&'a T> {
+    root.system.as_ref().and_then(|label| systems.get(label)).or(system)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub base_delay_secs: f64,
+    pub max_delay_secs: f64,
+    /// Fraction (0.0 to 1.0) of each delay that may be randomly shaved off.
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig { max_attempts: 10, base_delay_secs: 5.0, max_delay_secs: 900.0, jitter: 0.2 }
+    }
+}
+
+impl RetryConfig {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: Duration::from_secs_f64(self.base_delay_secs),
+            max_delay: Duration::from_secs_f64(self.max_delay_secs),
+            jitter: self.jitter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimestampConfig {
+    /// Timezone the SDRTrunk host writes filename timestamps in.
+    pub timezone: SourceTimezone,
+    pub format: TimestampFormat,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        TimestampConfig { timezone: SourceTimezone::Local, format: TimestampFormat::Original }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Trust the bundled public web roots in addition to `ca_bundle`.
+    pub system_roots: bool,
+    /// PEM file of extra CA certificates, e.g. a private CA.
+    pub ca_bundle: Option<PathBuf>,
+    /// Base64 SHA-256 hashes of acceptable server public keys (SPKI), optionally prefixed `sha256/`.
+    pub pinned_spki_sha256: Vec<String>,
+    /// PEM certificate chain presented to the server for mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key matching `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// Skip server certificate verification entirely. Only for testing.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsConfig {
+    /// The part of `[tls]` every HTTP service is verified with: the public roots plus
+    /// `ca_bundle`, without the pins, client certificate or `insecure_skip_verify` that
+    /// describe the `[upload]` server.
+    pub fn shared(&self) -> TlsConfig {
+        TlsConfig { ca_bundle: self.ca_bundle.clone(), ..TlsConfig::default() }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            system_roots: true,
+            ca_bundle: None,
+            pinned_spki_sha256: Vec::new(),
+            client_cert: None,
+            client_key: None,
+            insecure_skip_verify: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// How long in-flight uploads may keep running after SIGINT/SIGTERM before they are
+    /// abandoned and left in the queue for the next run.
+    pub deadline_secs: f64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig { deadline_secs: 30.0 }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn deadline(&self) -> Duration {
+        Duration::from_secs_f64(self.deadline_secs)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `EnvFilter` directives: a level such as `"info"`, or rules narrowed by target or span
+    /// field, e.g. `"info,uploader[call{talkgroup=1234}]=debug"`. Re-read on SIGHUP.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig { level: "info".to_string(), format: LogFormat::Text }
+    }
+}
+
+/// The built-in HTTP server for `/metrics`, `/healthz`, `/readyz` and `/status`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Address to listen on, e.g. `"127.0.0.1:9898"`. The server is off when unset.
+    pub listen: Option<SocketAddr>,
+    /// `/readyz` fails while more calls than this are waiting in the queue.
+    pub max_queue_depth: usize,
+    /// `/healthz` fails if the intake loop hasn't run for this long.
+    pub stall_secs: f64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig { listen: None, max_queue_depth: 1000, stall_secs: 30.0 }
+    }
+}
+
+impl HttpConfig {
+    pub fn stall(&self) -> Duration {
+        Duration::from_secs_f64(self.stall_secs)
+    }
+}
+
+/// Call events published to an MQTT broker once each sink has delivered a call or given up
+/// on it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Broker address, `mqtt://host[:port]`. Publishing is off when unset.
+    pub broker: Option<String>,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic for each call's events.
+    pub topic: EventTemplate,
+    /// 0 (at most once), 1 (at least once) or 2 (exactly once).
+    pub qos: u8,
+    /// Have the broker keep each topic's last event for clients that subscribe later.
+    pub retain: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            broker: None,
+            client_id: "sdrtrunk-uploader".to_string(),
+            username: None,
+            password: None,
+            topic: "scanner/{talkgroup}".parse().unwrap(),
+            qos: 1,
+            retain: false,
+        }
+    }
+}
+
+impl MqttConfig {
+    /// Host and port of `broker`, if it is set and valid.
+    pub fn broker_address(&self) -> Option<Result<(String, u16), String>> {
+        let broker = self.broker.as_deref()?;
+        Some(match Url::parse(broker) {
+            Ok(url) if url.scheme() != "mqtt" => Err(format!("unsupported scheme {:?}, expected mqtt", url.scheme())),
+            Ok(url) => match url.host_str() {
+                Some(host) if !host.is_empty() => Ok((host.to_string(), url.port().unwrap_or(1883))),
+                _ => Err("no host".to_string()),
+            },
+            Err(e) => Err(e.to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    /// Sinks whose calls are reported; every sink if empty.
+    pub sinks: Vec<String>,
+    /// Also report calls a sink gave up on, not just delivered ones.
+    pub failures: bool,
+    /// Request body, the call event as JSON if unset. Values are JSON-escaped and may be empty,
+    /// so every placeholder belongs inside quotes.
+    pub body: Option<EventTemplate>,
+    /// Extra request headers, e.g. `Authorization`.
+    pub headers: HashMap<String, String>,
+    /// Signs each body with HMAC-SHA256, sent as `sha256=<hex>` in `signature_header`.
+    pub secret: Option<String>,
+    pub signature_header: String,
+    /// Webhooks are retried in memory and dropped at shutdown, so by default they give up
+    /// sooner than uploads.
+    pub retry: RetryConfig,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            name: String::new(),
+            url: String::new(),
+            sinks: Vec::new(),
+            failures: false,
+            body: None,
+            headers: HashMap::new(),
+            secret: None,
+            signature_header: "X-Signature-256".to_string(),
+            retry: RetryConfig { max_attempts: 5, base_delay_secs: 2.0, max_delay_secs: 60.0, jitter: 0.2 },
+        }
+    }
+}
+
+/// Settings that can be given on the command line or through the environment.
+/// Flags win over environment variables, and both win over the config file.
+#[derive(Debug, Clone, Default, Args)]
+pub struct Overrides {
+    /// Config file to load [default: uploader.toml if it exists]
+    #[arg(long, short, global = true, env = "UPLOADER_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Directory to watch, in place of the config file's roots; may be repeated or comma-separated
+    #[arg(long = "watch", global = true, env = "MONITORED_DIRECTORY", value_delimiter = ',')]
+    pub watch_directories: Vec<PathBuf>,
+    /// Seconds without events for a call before its files are paired
+    #[arg(long, global = true, env = "DEBOUNCE_SECS")]
+    pub debounce_secs: Option<f64>,
+    /// Seconds a file must stop changing before it is uploaded
+    #[arg(long, global = true, env = "SETTLE_SECS")]
+    pub settle_secs: Option<f64>,
+    /// URL that calls are POSTed to
+    #[arg(long, global = true, env = "UPLOAD_ENDPOINT")]
+    pub endpoint: Option<String>,
+    /// API key sent with every upload
+    #[arg(long, global = true, env = "UPLOAD_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+    /// Total time allowed for one upload request
+    #[arg(long, global = true, env = "UPLOAD_TIMEOUT_SECS")]
+    pub timeout_secs: Option<f64>,
+    /// Time allowed to establish a connection
+    #[arg(long, global = true, env = "UPLOAD_CONNECT_TIMEOUT_SECS")]
+    pub connect_timeout_secs: Option<f64>,
+    /// Number of uploads that may be in flight at once
+    #[arg(long, global = true, env = "UPLOAD_WORKERS")]
+    pub workers: Option<usize>,
+    /// Radio id sent when a call has no FROM: omit, empty or sentinel:<value>
+    #[arg(long, global = true, env = "UNKNOWN_RADIO_ID")]
+    pub unknown_radio_id: Option<UnknownRadioId>,
+    /// SQLite file holding the upload queue
+    #[arg(long, global = true, env = "QUEUE_PATH")]
+    pub queue_path: Option<PathBuf>,
+    /// Where permanently failed calls are moved
+    #[arg(long, global = true, env = "DEAD_LETTER_DIRECTORY")]
+    pub dead_letter_directory: Option<PathBuf>,
+    /// SQLite file recording already uploaded calls
+    #[arg(long, global = true, env = "LEDGER_PATH")]
+    pub ledger_path: Option<PathBuf>,
+    /// Don't scan for missed calls at startup
+    #[arg(long, global = true, env = "SKIP_BACKFILL", value_parser = clap::builder::BoolishValueParser::new())]
+    pub skip_backfill: bool,
+    /// Only backfill calls modified within this many hours
+    #[arg(long, global = true, env = "BACKFILL_MAX_AGE_HOURS")]
+    pub backfill_max_age_hours: Option<f64>,
+    /// Backfill order: oldest_first or newest_first
+    #[arg(long, global = true, env = "BACKFILL_ORDER")]
+    pub backfill_order: Option<BackfillOrder>,
+    /// Attempts before a call is dead-lettered
+    #[arg(long, global = true, env = "RETRY_MAX_ATTEMPTS")]
+    pub retry_max_attempts: Option<u32>,
+    /// Delay before the first retry; doubles with each attempt
+    #[arg(long, global = true, env = "RETRY_BASE_DELAY_SECS")]
+    pub retry_base_delay_secs: Option<f64>,
+    /// Longest delay between retries
+    #[arg(long, global = true, env = "RETRY_MAX_DELAY_SECS")]
+    pub retry_max_delay_secs: Option<f64>,
+    /// Fraction of each retry delay that may be randomly shaved off
+    #[arg(long, global = true, env = "RETRY_JITTER")]
+    pub retry_jitter: Option<f64>,
+    /// Timezone of the SDRTrunk host: local, UTC or an IANA name
+    #[arg(long, global = true, env = "SOURCE_TIMEZONE")]
+    pub source_timezone: Option<SourceTimezone>,
+    /// Timestamp sent to the server: rfc3339, unix, unix_millis or original
+    #[arg(long, global = true, env = "TIMESTAMP_FORMAT")]
+    pub timestamp_format: Option<TimestampFormat>,
+    /// PEM file of extra CA certificates to trust
+    #[arg(long, global = true, env = "UPLOAD_CA_BUNDLE")]
+    pub ca_bundle: Option<PathBuf>,
+    /// Base64 SHA-256 SPKI hash the server must present; may be repeated or comma-separated
+    #[arg(long = "pin-spki", global = true, env = "UPLOAD_PINNED_SPKI", value_delimiter = ',')]
+    pub pinned_spki_sha256: Vec<String>,
+    /// PEM client certificate chain for mutual TLS
+    #[arg(long, global = true, env = "UPLOAD_CLIENT_CERT")]
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for the client certificate
+    #[arg(long, global = true, env = "UPLOAD_CLIENT_KEY")]
+    pub client_key: Option<PathBuf>,
+    /// Disable server certificate verification (testing only)
+    #[arg(
+        long,
+        global = true,
+        env = "UPLOAD_INSECURE_SKIP_TLS_VERIFY",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    pub insecure_skip_tls_verify: bool,
+    /// Seconds in-flight uploads may take to finish after a shutdown signal
+    #[arg(long, global = true, env = "SHUTDOWN_DEADLINE_SECS")]
+    pub shutdown_deadline_secs: Option<f64>,
+    /// Log filter, e.g. debug or info,uploader[call{talkgroup=1234}]=debug
+    #[arg(long, global = true, env = "LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Log output: text or json
+    #[arg(long, global = true, env = "LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Address for the metrics and health HTTP server, e.g. 127.0.0.1:9898
+    #[arg(long, global = true, env = "HTTP_LISTEN")]
+    pub http_listen: Option<SocketAddr>,
+    /// MQTT broker to publish call events to, e.g. mqtt://localhost:1883
+    #[arg(long, global = true, env = "MQTT_BROKER")]
+    pub mqtt_broker: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "failed to read {:?}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "failed to parse {:?}: {}", path, e),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(overrides: &Overrides) -> Result<Config, ConfigError> {
+        let mut config = match &overrides.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => Config::default(),
+        };
+        config.apply(overrides);
+        for path in &config.watch.directories {
+            if !config.watch.roots.iter().any(|root| &root.path == path) {
+                config.watch.roots.push(RootConfig { path: path.clone(), ..RootConfig::default() });
+            }
+        }
+        if config.sinks.is_empty() {
+            config.sinks.push(SinkConfig {
+                name: DEFAULT_SINK.to_string(),
+                retry: None,
+                kind: SinkKind::Multipart(MultipartSinkConfig::default()),
+            });
+        }
+        Ok(config)
+    }
+
+    /// The root `path` is under, preferring the deepest when roots are nested.
+    pub fn root_for(&self, path: &Path) -> Option<&RootConfig> {
+        self.watch
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
+    pub fn sink_names(&self) -> Vec<&str> {
+        self.sinks.iter().map(|sink| sink.name.as_str()).collect()
+    }
+
+    /// Parses a recording's filename with the pattern of the root it was found under.
+    pub fn parse_call(&self, path: &Path) -> Option<CallRecord> {
+        let filename = path.file_name()?.to_str()?;
+        match self.root_for(path) {
+            Some(root) => root.parse_filename(filename),
+            None => FilenamePattern::sdrtrunk().parse(filename),
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    fn apply(&mut self, overrides: &Overrides) {
+        if !overrides.watch_directories.is_empty() {
+            // Replaces the file's roots, but a root named again keeps its settings.
+            self.watch.roots.retain(|root| overrides.watch_directories.contains(&root.path));
+            self.watch.directories = overrides.watch_directories.clone();
+        }
+        set(&mut self.watch.debounce_secs, &overrides.debounce_secs);
+        set(&mut self.watch.settle_secs, &overrides.settle_secs);
+        set(&mut self.upload.endpoint, &overrides.endpoint);
+        if overrides.api_key.is_some() {
+            self.upload.api_key = overrides.api_key.clone();
+        }
+        set(&mut self.upload.timeout_secs, &overrides.timeout_secs);
+        set(&mut self.upload.connect_timeout_secs, &overrides.connect_timeout_secs);
+        set(&mut self.upload.workers, &overrides.workers);
+        set(&mut self.upload.unknown_radio_id, &overrides.unknown_radio_id);
+        set(&mut self.queue.path, &overrides.queue_path);
+        set(&mut self.queue.dead_letter_directory, &overrides.dead_letter_directory);
+        set(&mut self.ledger.path, &overrides.ledger_path);
+        self.backfill.on_startup &= !overrides.skip_backfill;
+        if overrides.backfill_max_age_hours.is_some() {
+            self.backfill.max_age_hours = overrides.backfill_max_age_hours;
+        }
+        set(&mut self.backfill.order, &overrides.backfill_order);
+        set(&mut self.retry.max_attempts, &overrides.retry_max_attempts);
+        set(&mut self.retry.base_delay_secs, &overrides.retry_base_delay_secs);
+        set(&mut self.retry.max_delay_secs, &overrides.retry_max_delay_secs);
+        set(&mut self.retry.jitter, &overrides.retry_jitter);
+        set(&mut self.timestamp.timezone, &overrides.source_timezone);
+        set(&mut self.timestamp.format, &overrides.timestamp_format);
+        if overrides.ca_bundle.is_some() {
+            self.tls.ca_bundle = overrides.ca_bundle.clone();
+        }
+        if !overrides.pinned_spki_sha256.is_empty() {
+            self.tls.pinned_spki_sha256 = overrides.pinned_spki_sha256.clone();
+        }
+        if overrides.client_cert.is_some() {
+            self.tls.client_cert = overrides.client_cert.clone();
+        }
+        if overrides.client_key.is_some() {
+            self.tls.client_key = overrides.client_key.clone();
+        }
+        self.tls.insecure_skip_verify |= overrides.insecure_skip_tls_verify;
+        set(&mut self.shutdown.deadline_secs, &overrides.shutdown_deadline_secs);
+        set(&mut self.log.level, &overrides.log_level);
+        set(&mut self.log.format, &overrides.log_format);
+        if overrides.http_listen.is_some() {
+            self.http.listen = overrides.http_listen;
+        }
+        if overrides.mqtt_broker.is_some() {
+            self.mqtt.broker = overrides.mqtt_broker.clone();
+        }
+    }
+
+    /// Checks the whole configuration and returns every problem found, not just the first.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.watch.roots.is_empty() {
+            problems.push("watch: no directories to watch".to_string());
+        }
+        let mut root_paths = HashSet::new();
+        for root in &self.watch.roots {
+            let key = format!("watch.roots[{:?}]", root.path);
+            if !root.path.is_dir() {
+                problems.push(format!("{}: not a directory", key));
+            }
+            if !root_paths.insert(&root.path) {
+                problems.push(format!("{}: listed more than once", key));
+            }
+            if root.system.as_deref() == Some("") {
+                problems.push(format!("{}.system: empty", key));
+            }
+            if let Some(endpoint) = &root.endpoint {
+                check_endpoint(&format!("{}.endpoint", key), endpoint, &mut problems);
+            }
+            if root.api_key.as_deref() == Some("") {
+                problems.push(format!("{}.api_key: empty", key));
+            }
+            if let Some(header) = &root.api_key_header {
+                if reqwest::header::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                    problems.push(format!("{}.api_key_header: {:?} is not a valid header name", key, header));
+                }
+            }
+        }
+
+        for (key, secs) in [
+            ("debounce_secs", self.watch.debounce_secs),
+            ("dedupe_window_secs", self.watch.dedupe_window_secs),
+            ("settle_secs", self.watch.settle_secs),
+        ] {
+            if !secs.is_finite() || secs < 0.0 {
+                problems.push(format!("watch.{}: must not be negative", key));
+            }
+        }
+
+        let upload = &self.upload;
+        if !upload.endpoint.is_empty() {
+            check_endpoint("upload.endpoint", &upload.endpoint, &mut problems);
+        } else if self.watch.roots.iter().any(|root| root.endpoint.is_none())
+            && self.sinks.iter().any(|sink| matches!(&sink.kind, SinkKind::Multipart(m) if m.endpoint.is_none()))
+        {
+            problems.push("upload.endpoint: not set, and not every watch root or multipart sink sets its own".to_string());
+        }
+        if upload.api_key.as_deref() == Some("") {
+            problems.push("upload.api_key: empty".to_string());
+        }
+        if reqwest::header::HeaderName::from_bytes(upload.api_key_header.as_bytes()).is_err() {
+            problems.push(format!("upload.api_key_header: {:?} is not a valid header name", upload.api_key_header));
+        }
+        if !is_positive(upload.timeout_secs) {
+            problems.push("upload.timeout_secs: must be greater than zero".to_string());
+        }
+        if !is_positive(upload.connect_timeout_secs) {
+            problems.push("upload.connect_timeout_secs: must be greater than zero".to_string());
+        }
+        if upload.workers == 0 {
+            problems.push("upload.workers: must be at least 1".to_string());
+        }
+        if upload.unknown_radio_id == UnknownRadioId::Sentinel(String::new()) {
+            problems.push("upload.unknown_radio_id: empty sentinel, use \"empty\" instead".to_string());
+        }
+        let fields = &upload.fields;
+        let mut seen = HashSet::new();
+        for (key, name) in [
+            ("talkgroup_id", &fields.talkgroup_id),
+            ("timestamp", &fields.timestamp),
+            ("radio_id", &fields.radio_id),
+            ("mp3", &fields.mp3),
+            ("transcription", &fields.transcription),
+            ("system", &fields.system),
+        ] {
+            if name.is_empty() {
+                problems.push(format!("upload.fields.{}: empty", key));
+            } else if !seen.insert(name) {
+                problems.push(format!("upload.fields.{}: {:?} is used by another field", key, name));
+            }
+        }
+
+        for (key, path) in [("queue.path", &self.queue.path), ("ledger.path", &self.ledger.path)] {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                if !parent.is_dir() {
+                    problems.push(format!("{}: directory {:?} does not exist", key, parent));
+                }
+            }
+        }
+
+        if self.backfill.max_age_hours.is_some_and(|hours| !is_positive(hours)) {
+            problems.push("backfill.max_age_hours: must be greater than zero".to_string());
+        }
+
+        check_retry("retry", &self.retry, &mut problems);
+
+        let mut sink_names = HashSet::new();
+        for sink in &self.sinks {
+            let key = format!("sinks[{:?}]", sink.name);
+            if !is_valid_name(&sink.name) {
+                problems.push(format!("{}.name: use only letters, digits, '-' and '_'", key));
+            }
+            if !sink_names.insert(&sink.name) {
+                problems.push(format!("{}.name: used by another sink", key));
+            }
+            if let Some(retry) = &sink.retry {
+                check_retry(&format!("{}.retry", key), retry, &mut problems);
+            }
+            match &sink.kind {
+                SinkKind::Multipart(multipart) => {
+                    if let Some(endpoint) = &multipart.endpoint {
+                        check_endpoint(&format!("{}.endpoint", key), endpoint, &mut problems);
+                    }
+                    if multipart.api_key.as_deref() == Some("") {
+                        problems.push(format!("{}.api_key: empty", key));
+                    }
+                    if let Some(header) = &multipart.api_key_header {
+                        if reqwest::header::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                            problems.push(format!("{}.api_key_header: {:?} is not a valid header name", key, header));
+                        }
+                    }
+                }
+                SinkKind::RdioScanner(rdio) => {
+                    check_endpoint(&format!("{}.url", key), &rdio.url, &mut problems);
+                    check_api_key(&key, &rdio.api_key, &mut problems);
+                    check_systems(&key, &self.watch.roots, |root| rdio.system_id(root).is_some(), &mut problems);
+                }
+                SinkKind::OpenMhz(openmhz) => {
+                    check_endpoint(&format!("{}.url", key), &openmhz.url, &mut problems);
+                    check_api_key(&key, &openmhz.api_key, &mut problems);
+                    check_systems(&key, &self.watch.roots, |root| openmhz.short_name(root).is_some(), &mut problems);
+                }
+                SinkKind::Broadcastify(broadcastify) => {
+                    check_endpoint(&format!("{}.url", key), &broadcastify.url, &mut problems);
+                    check_api_key(&key, &broadcastify.api_key, &mut problems);
+                    check_systems(&key, &self.watch.roots, |root| broadcastify.system_id(root).is_some(), &mut problems);
+                }
+                SinkKind::S3(s3) => {
+                    check_endpoint(&format!("{}.endpoint", key), &s3.endpoint, &mut problems);
+                    if s3.bucket.is_empty() {
+                        problems.push(format!("{}.bucket: not set", key));
+                    }
+                    if s3.region.is_empty() {
+                        problems.push(format!("{}.region: not set", key));
+                    }
+                    if s3.credentials().is_none() {
+                        problems.push(format!(
+                            "{}: access_key_id and secret_access_key not set, in the config or as AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                            key
+                        ));
+                    }
+                    if s3.key.to_string() == s3.transcript_key.to_string() {
+                        problems.push(format!("{}.transcript_key: same as key, the transcript would replace the mp3", key));
+                    }
+                }
+                SinkKind::Sqlite(sqlite) => {
+                    if sqlite.path == self.queue.path || sqlite.path == self.ledger.path {
+                        problems.push(format!("{}.path: must not be the queue or ledger database", key));
+                    }
+                }
+            }
+        }
+
+        let tls = &self.tls;
+        if tls.client_cert.is_some() != tls.client_key.is_some() {
+            problems.push("tls: client_cert and client_key must be given together".to_string());
+        }
+        if tls.insecure_skip_verify && !tls.pinned_spki_sha256.is_empty() {
+            problems.push("tls: insecure_skip_verify disables pinned_spki_sha256".to_string());
+        }
+        if !tls.system_roots && tls.ca_bundle.is_none() && !tls.insecure_skip_verify {
+            problems.push("tls: system_roots is off and no ca_bundle is set, so no server can be trusted".to_string());
+        }
+        if let Err(e) = tls::client_config(tls) {
+            problems.push(format!("tls: {}", e));
+        }
+        // Other services share only the roots and `ca_bundle`; pins and the client certificate
+        // are for the `[upload]` server.
+        let others = self
+            .sinks
+            .iter()
+            .filter(|sink| !matches!(sink.kind, SinkKind::Multipart(_) | SinkKind::Sqlite(_)))
+            .map(|sink| format!("sinks[{:?}]", sink.name))
+            .chain(self.webhooks.iter().map(|webhook| format!("webhooks[{:?}]", webhook.name)));
+        for other in others {
+            if !tls.pinned_spki_sha256.is_empty() {
+                problems.push(format!("tls.pinned_spki_sha256: applies only to multipart sinks, {} would not be pinned", other));
+            }
+            if tls.client_cert.is_some() {
+                problems.push(format!("tls.client_cert: applies only to multipart sinks, {} would not send it", other));
+            }
+        }
+
+        if !self.shutdown.deadline_secs.is_finite() || self.shutdown.deadline_secs < 0.0 {
+            problems.push("shutdown.deadline_secs: must not be negative".to_string());
+        }
+
+        if !is_positive(self.http.stall_secs) {
+            problems.push("http.stall_secs: must be greater than zero".to_string());
+        }
+
+        let mqtt = &self.mqtt;
+        if let Some(Err(e)) = mqtt.broker_address() {
+            problems.push(format!("mqtt.broker: {}", e));
+        }
+        if mqtt.client_id.is_empty() {
+            problems.push("mqtt.client_id: empty".to_string());
+        }
+        if mqtt.password.is_some() && mqtt.username.is_none() {
+            problems.push("mqtt.password: set without a username".to_string());
+        }
+        if mqtt.topic.to_string().is_empty() || mqtt.topic.to_string().contains(['+', '#']) {
+            problems.push("mqtt.topic: must be non-empty and free of the wildcards '+' and '#'".to_string());
+        }
+        if mqtt.qos > 2 {
+            problems.push("mqtt.qos: must be 0, 1 or 2".to_string());
+        }
+
+        let mut webhook_names = HashSet::new();
+        for webhook in &self.webhooks {
+            let key = format!("webhooks[{:?}]", webhook.name);
+            if !is_valid_name(&webhook.name) {
+                problems.push(format!("{}.name: use only letters, digits, '-' and '_'", key));
+            }
+            if !webhook_names.insert(&webhook.name) {
+                problems.push(format!("{}.name: used by another webhook", key));
+            }
+            check_endpoint(&format!("{}.url", key), &webhook.url, &mut problems);
+            for sink in webhook.sinks.iter().filter(|sink| !sink_names.contains(sink)) {
+                problems.push(format!("{}.sinks: no sink named {:?}", key, sink));
+            }
+            if let Some(body) = &webhook.body {
+                // Any field may be empty or hold text, so only placeholders inside JSON strings
+                // are safe; try both.
+                for sample in ["", "text"] {
+                    let fields = FIELDS.iter().chain(EVENT_FIELDS).map(|field| (*field, sample.to_string())).collect();
+                    if let Err(e) = serde_json::from_str::<serde_json::Value>(&body.render(&fields)) {
+                        problems.push(format!(
+                            "{}.body: not valid JSON with {:?} for every field ({}), put each placeholder inside quotes",
+                            key, sample, e
+                        ));
+                        break;
+                    }
+                }
+            }
+            for (name, value) in &webhook.headers {
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    problems.push(format!("{}.headers: {:?} is not a valid header name", key, name));
+                } else if reqwest::header::HeaderValue::from_str(value).is_err() {
+                    problems.push(format!("{}.headers.{}: not a valid header value", key, name));
+                }
+            }
+            if webhook.secret.as_deref() == Some("") {
+                problems.push(format!("{}.secret: empty", key));
+            }
+            if reqwest::header::HeaderName::from_bytes(webhook.signature_header.as_bytes()).is_err() {
+                problems.push(format!("{}.signature_header: {:?} is not a valid header name", key, webhook.signature_header));
+            }
+            check_retry(&format!("{}.retry", key), &webhook.retry, &mut problems);
+        }
+
+        if let Err(e) = logging::parse_filter(&self.log.level) {
+            problems.push(format!("log.level: {}", e));
+        }
+
+        problems
+    }
+}
+
+fn check_retry(key: &str, retry: &RetryConfig, problems: &mut Vec<String>) {
+    if retry.max_attempts == 0 {
+        problems.push(format!("{}.max_attempts: must be at least 1", key));
+    }
+    if !is_positive(retry.base_delay_secs) && retry.base_delay_secs != 0.0 {
+        problems.push(format!("{}.base_delay_secs: must not be negative", key));
+    }
+    if !retry.max_delay_secs.is_finite() || retry.max_delay_secs < retry.base_delay_secs {
+        problems.push(format!("{0}.max_delay_secs: must not be less than {0}.base_delay_secs", key));
+    }
+    if !(0.0..=1.0).contains(&retry.jitter) {
+        problems.push(format!("{}.jitter: must be between 0.0 and 1.0", key));
+    }
+}
+
+fn check_api_key(key: &str, api_key: &str, problems: &mut Vec<String>) {
+    if api_key.is_empty() {
+        problems.push(format!("{}.api_key: not set", key));
+    }
+}
+
+/// Every watch root needs a system the sink can file its calls under.
+fn check_systems(key: &str, roots: &[RootConfig], found: impl Fn(&RootConfig) -> bool, problems: &mut Vec<String>) {
+    for root in roots.iter().filter(|root| !found(root)) {
+        problems.push(format!(
+            "{}.system: none for {}, set system or add its label to systems",
+            key,
+            root.path.display()
+        ));
+    }
+}
+
+fn check_endpoint(key: &str, endpoint: &str, problems: &mut Vec<String>) {
+    match Url::parse(endpoint) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(url) => problems.push(format!("{}: unsupported scheme {:?}", key, url.scheme())),
+        Err(e) => problems.push(format!("{}: {}", key, e)),
+    }
+}
+
+/// Sink and webhook names, which end up in file names, metrics labels and the ledger.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_positive(secs: f64) -> bool {
+    secs.is_finite() && secs > 0.0
+}
+
+fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+    if let Some(value) = value {
+        *target = value.clone();
+    }
+}
@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Why a call ended up in the dead-letter directory, written next to it as
+/// `<stem>.<sink>.json`.
+#[derive(Debug, Serialize)]
+pub struct DeadLetterReason<'a> {
+    pub mp3_path: &'a Path,
+    pub txt_path: &'a Path,
+    pub sink: &'a str,
+    pub error: String,
+    pub status: Option<u16>,
+    pub attempts: u32,
+    pub failed_at: u64,
+}
+
+/// Moves a permanently failed mp3/txt pair out of the watched tree into `dir` and writes a
+/// JSON sidecar explaining the failure. With `keep_originals` the pair is copied instead,
+/// for calls other sinks still need. Files that no longer exist are skipped.
+///
+/// The pair keeps its directory path under `dir`, e.g. `dir/var/lib/sdrtrunk/recordings/...`,
+/// since recorders in different roots can write calls with the same name.
+pub fn bury(dir: &Path, reason: &DeadLetterReason, keep_originals: bool) -> io::Result<PathBuf> {
+    let source_dir = reason.mp3_path.parent().unwrap_or(Path::new(""));
+    let dir = &dir.join(source_dir.components().filter(|c| matches!(c, Component::Normal(_))).collect::<PathBuf>());
+    fs::create_dir_all(dir)?;
+    for path in [reason.mp3_path, reason.txt_path] {
+        if let Some(name) = path.file_name() {
+            let result = if keep_originals { fs::copy(path, dir.join(name)).map(|_| ()) } else { move_file(path, &dir.join(name)) };
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                other => other?,
+            }
+        }
+    }
+    let stem = reason.mp3_path.file_stem().unwrap_or_default().to_string_lossy();
+    let sidecar = dir.join(format!("{}.{}.json", stem, reason.sink));
+    fs::write(&sidecar, serde_json::to_vec_pretty(reason)?)?;
+    Ok(sidecar)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// `fs::rename`, falling back to copy-and-delete when the destination is on another filesystem.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(e),
+        Err(_) => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+    }
+}
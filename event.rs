@@ -0,0 +1,158 @@
+use crate::{
+    config::{Config, RootConfig},
+    ledger::LedgerEntry,
+    mqtt,
+    queue::Job,
+    sink::{Call, CallFiles, UploadError},
+    webhook::Webhook,
+};
+use reqwest::Client;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+/// How long shutdown waits for webhooks still being sent or retried.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a call ended up at one sink, as published to MQTT and the default webhook body.
+#[derive(Debug, Serialize)]
+pub struct CallEvent {
+    pub stem: String,
+    pub sink: String,
+    /// RFC 3339, UTC.
+    pub timestamp: String,
+    pub system: Option<String>,
+    pub talkgroup: String,
+    pub talkgroup_alias: Option<String>,
+    pub radio_id: Option<String>,
+    pub radio_alias: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub transcript: String,
+    /// `"delivered"` or `"failed"`.
+    pub result: &'static str,
+    pub status: Option<u16>,
+    /// Id the destination gave the call, if it gave one.
+    pub call_id: Option<String>,
+    pub error: Option<String>,
+    pub attempts: u32,
+    /// Values for `template::FIELDS` and `template::EVENT_FIELDS`.
+    #[serde(skip)]
+    pub fields: HashMap<&'static str, String>,
+}
+
+impl CallEvent {
+    /// Describes how `job` ended up at its sink: the ledger entry it was delivered under, or
+    /// the error it failed with. `None` if the call's filename or timestamp can't be read.
+    pub async fn new(config: &Config, job: &Job, outcome: Result<&LedgerEntry, &UploadError>) -> Option<Self> {
+        let default_root = RootConfig::default();
+        let root = config.root_for(&job.mp3_path).unwrap_or(&default_root);
+        let filename = job.mp3_path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(record) = root.parse_filename(&filename) else {
+            debug!("No call event for an unrecognized filename");
+            return None;
+        };
+        // A failed call may be missing a file; its event goes out without what can't be read.
+        let files = CallFiles::read(&job.mp3_path, &job.txt_path).await.unwrap_or_else(|e| {
+            debug!("Failed to read call for its event: {}", e);
+            CallFiles { mp3_path: job.mp3_path.clone(), txt_path: job.txt_path.clone(), mp3: Vec::new(), txt: Vec::new() }
+        });
+        let call = Call { record, root, files: &files };
+        let mut fields = match call.fields(config.timestamp.timezone) {
+            Ok(fields) => fields,
+            Err(e) => {
+                warn!("No call event: {}", e);
+                return None;
+            }
+        };
+
+        let event = CallEvent {
+            stem: files.stem(),
+            sink: job.sink.clone(),
+            timestamp: fields["timestamp"].clone(),
+            system: call.system(),
+            talkgroup: call.record.to_id.clone(),
+            talkgroup_alias: call.record.to_alias.clone(),
+            radio_id: call.record.from_id.clone(),
+            radio_alias: call.record.from_alias.clone(),
+            duration_secs: fields["duration"].parse().ok(),
+            transcript: String::from_utf8_lossy(&files.txt).trim().to_string(),
+            result: if outcome.is_ok() { "delivered" } else { "failed" },
+            status: match outcome {
+                Ok(entry) => Some(entry.status),
+                Err(e) => e.status().map(|s| s.as_u16()),
+            },
+            call_id: outcome.ok().and_then(|entry| entry.call_id.clone()),
+            error: outcome.err().map(UploadError::to_string),
+            attempts: job.attempts + 1,
+            fields: HashMap::new(),
+        };
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        fields.extend([
+            ("sink", event.sink.clone()),
+            ("result", event.result.to_string()),
+            ("status", event.status.map(|s| s.to_string()).unwrap_or_default()),
+            ("call_id", text(&event.call_id)),
+            ("error", text(&event.error)),
+            ("transcript", event.transcript.clone()),
+            ("attempts", event.attempts.to_string()),
+        ]);
+        Some(CallEvent { fields, ..event })
+    }
+}
+
+/// Tells MQTT and webhooks how each call ended up at each sink, once the sink has delivered
+/// it or given up. Retries in between aren't reported.
+pub struct Notifier {
+    mqtt: Option<mqtt::Publisher>,
+    webhooks: Vec<Webhook>,
+    /// Webhooks being sent or waiting to be retried.
+    sending: Mutex<JoinSet<()>>,
+    config: Arc<Config>,
+}
+
+impl Notifier {
+    pub fn start(config: Arc<Config>, client: &Client) -> Self {
+        Notifier {
+            mqtt: mqtt::Publisher::start(&config.mqtt),
+            webhooks: config.webhooks.iter().map(|settings| Webhook::new(settings.clone(), client.clone())).collect(),
+            sending: Mutex::new(JoinSet::new()),
+            config,
+        }
+    }
+
+    pub async fn notify(&self, job: &Job, outcome: Result<&LedgerEntry, &UploadError>) {
+        let webhooks: Vec<&Webhook> = self.webhooks.iter().filter(|w| w.wants(&job.sink, outcome.is_ok())).collect();
+        if self.mqtt.is_none() && webhooks.is_empty() {
+            return;
+        }
+        let Some(event) = CallEvent::new(&self.config, job, outcome).await else {
+            return;
+        };
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(&event);
+        }
+        let mut sending = self.sending.lock().unwrap();
+        // Reap finished sends so the set only holds those still under way.
+        while sending.try_join_next().is_some() {}
+        for webhook in webhooks {
+            sending.spawn(webhook.send(&event));
+        }
+    }
+
+    /// Gives webhooks still under way, and MQTT events still queued, a moment to go out.
+    pub async fn close(&self) {
+        let mut sending = std::mem::take(&mut *self.sending.lock().unwrap());
+        let finished = tokio::time::timeout(CLOSE_TIMEOUT, async { while sending.join_next().await.is_some() {} }).await;
+        if finished.is_err() {
+            warn!(abandoned = sending.len(), "Shutting down with webhooks still unsent");
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.close().await;
+        }
+    }
+}
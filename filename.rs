@@ -0,0 +1,247 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::{path::Path, str::FromStr, sync::OnceLock};
+
+/// Everything SDRTrunk encodes in a recording's filename.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallRecord {
+    /// Start of the call as written by SDRTrunk, `YYYYMMDD_HHMMSS` in the recorder's local time.
+    pub timestamp: String,
+    pub system: Option<String>,
+    pub site: Option<String>,
+    pub channel: Option<String>,
+    /// Talkgroup (or radio, for private calls) the call was addressed to.
+    pub to_id: String,
+    pub to_alias: Option<String>,
+    /// The call went to a patch group rather than a plain talkgroup.
+    pub patch: bool,
+    /// Radio that transmitted, when SDRTrunk knew it.
+    pub from_id: Option<String>,
+    pub from_alias: Option<String>,
+    pub protocol: Option<String>,
+    pub encrypted: bool,
+}
+
+/// Grammar of SDRTrunk recording filenames, matched against the name without its extension.
+/// SDRTrunk replaces spaces and other unsafe characters in names with `_`.
+///
+/// ```text
+/// filename  = timestamp ["_"] [header] "_" ["_"] "TO_" to ["_FROM_" from] ["_" protocol] ["_ENCRYPTED"] "." ext
+/// timestamp = 8DIGIT "_" 6DIGIT                  ; YYYYMMDD_HHMMSS
+/// header    = system ["_" site ["_" channel]]    ; system and site are single words, channel takes the rest
+/// to        = ["P_"] 1*DIGIT ["_" alias]         ; "P_" marks a patch group
+/// from      = 1*DIGIT ["_" alias]
+/// protocol  = "APCO25" | "P25" | "P25P1" | "P25P2" | "DMR" | "NXDN" | "LTR" | "MPT1327" | "PASSPORT" | "NBFM" | "AM"
+/// ```
+///
+/// Examples of names this accepts:
+///
+/// ```text
+/// 20240101_120000Metro__TO_52197.mp3
+/// 20240101_120000Metro_Site1_Control_Channel__TO_52197_FROM_1504011.mp3
+/// 20240101_120000Metro__TO_52197_Fire_Dispatch_FROM_1504011_Engine_1.mp3
+/// 20240101_120000Metro__TO_P_1234_FROM_1504011.mp3
+/// 20240101_120000Metro_Site1__TO_52197_FROM_1504011_P25_ENCRYPTED.mp3
+/// 20240101_120000__TO_52197.mp3
+/// ```
+const SDRTRUNK_FILENAME: &str = r"(?x)
+    ^(?P<timestamp>\d{8}_\d{6})
+    _?
+    (?:(?P<system>[^_]+)(?:_(?P<site>[^_]+))?(?:_(?P<channel>.+?))?)?
+    __?TO_
+    (?P<patch>P_)?(?P<to>\d+)
+    (?:_(?P<to_alias>.+?))??
+    (?:_FROM_(?P<from>\d+)(?:_(?P<from_alias>.+?))??)?
+    (?:_(?P<protocol>APCO25|P25P1|P25P2|P25|DMR|NXDN|LTR|MPT1327|PASSPORT|NBFM|AM))?
+    (?P<encrypted>_ENCRYPTED)?
+    $";
+
+/// A filename regex whose named groups fill in a `CallRecord`. `timestamp` (as
+/// `YYYYMMDD_HHMMSS`) and `to` are required; `system`, `site`, `channel`, `to_alias`, `from`,
+/// `from_alias` and `protocol` are used when present, and the `patch` and `encrypted` groups
+/// set their flags when they match anything.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FilenamePattern(Regex);
+
+impl FilenamePattern {
+    /// The pattern for SDRTrunk's own naming scheme.
+    pub fn sdrtrunk() -> &'static FilenamePattern {
+        static PATTERN: OnceLock<FilenamePattern> = OnceLock::new();
+        PATTERN.get_or_init(|| FilenamePattern(Regex::new(SDRTRUNK_FILENAME).unwrap()))
+    }
+
+    /// Parses a recording filename (with or without extension) into a `CallRecord`.
+    pub fn parse(&self, filename: &str) -> Option<CallRecord> {
+        let stem = Path::new(filename).file_stem()?.to_str()?;
+        let cap = self.0.captures(stem)?;
+        let text = |name: &str| cap.name(name).map(|m| m.as_str().to_string());
+        Some(CallRecord {
+            timestamp: text("timestamp")?,
+            system: text("system"),
+            site: text("site"),
+            channel: text("channel"),
+            to_id: text("to")?,
+            to_alias: text("to_alias"),
+            patch: cap.name("patch").is_some(),
+            from_id: text("from"),
+            from_alias: text("from_alias"),
+            protocol: text("protocol"),
+            encrypted: cap.name("encrypted").is_some(),
+        })
+    }
+}
+
+impl FromStr for FilenamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regex = Regex::new(s).map_err(|e| e.to_string())?;
+        for group in ["timestamp", "to"] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                return Err(format!("pattern has no (?P<{}>...) group", group));
+            }
+        }
+        Ok(FilenamePattern(regex))
+    }
+}
+
+impl TryFrom<String> for FilenamePattern {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(filename: &str) -> Option<CallRecord> {
+        FilenamePattern::sdrtrunk().parse(filename)
+    }
+
+    fn owned(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn system_and_talkgroup_only() {
+        assert_eq!(
+            parse("20240101_120000Metro__TO_52197.mp3").unwrap(),
+            CallRecord {
+                timestamp: "20240101_120000".to_string(),
+                system: owned("Metro"),
+                to_id: "52197".to_string(),
+                ..CallRecord::default()
+            }
+        );
+    }
+
+    #[test]
+    fn site_channel_and_source() {
+        let record = parse("20240101_120000Metro_Site1_Control_Channel__TO_52197_FROM_1504011.mp3").unwrap();
+        assert_eq!(record.system, owned("Metro"));
+        assert_eq!(record.site, owned("Site1"));
+        assert_eq!(record.channel, owned("Control_Channel"));
+        assert_eq!(record.to_id, "52197");
+        assert_eq!(record.to_alias, None);
+        assert_eq!(record.from_id, owned("1504011"));
+    }
+
+    #[test]
+    fn aliases() {
+        let record = parse("20240101_120000Metro__TO_52197_Fire_Dispatch_FROM_1504011_Engine_1.mp3").unwrap();
+        assert_eq!(record.to_id, "52197");
+        assert_eq!(record.to_alias, owned("Fire_Dispatch"));
+        assert_eq!(record.from_id, owned("1504011"));
+        assert_eq!(record.from_alias, owned("Engine_1"));
+    }
+
+    #[test]
+    fn patch_group() {
+        let record = parse("20240101_120000Metro__TO_P_1234_FROM_1504011.mp3").unwrap();
+        assert!(record.patch);
+        assert_eq!(record.to_id, "1234");
+        assert_eq!(record.from_id, owned("1504011"));
+    }
+
+    #[test]
+    fn protocol_and_encryption() {
+        let record = parse("20240101_120000Metro_Site1__TO_52197_FROM_1504011_P25_ENCRYPTED.mp3").unwrap();
+        assert_eq!(record.site, owned("Site1"));
+        assert_eq!(record.from_id, owned("1504011"));
+        assert_eq!(record.from_alias, None);
+        assert_eq!(record.protocol, owned("P25"));
+        assert!(record.encrypted);
+    }
+
+    #[test]
+    fn no_system() {
+        let record = parse("20240101_120000__TO_52197.mp3").unwrap();
+        assert_eq!(record.system, None);
+        assert_eq!(record.to_id, "52197");
+    }
+
+    #[test]
+    fn protocol_like_words_inside_aliases() {
+        let record = parse("20240101_120000Metro__TO_52197_AM_Net_FROM_5_DMR_Unit.mp3").unwrap();
+        assert_eq!(record.to_alias, owned("AM_Net"));
+        assert_eq!(record.from_alias, owned("DMR_Unit"));
+        assert_eq!(record.protocol, None);
+    }
+
+    #[test]
+    fn protocol_like_word_ending_an_alias_is_the_protocol() {
+        let record = parse("20240101_120000Metro__TO_52197_Air_AM.mp3").unwrap();
+        assert_eq!(record.to_alias, owned("Air"));
+        assert_eq!(record.protocol, owned("AM"));
+    }
+
+    #[test]
+    fn underscore_in_system_name_splits_into_site() {
+        // "Metro Area" is written "Metro_Area"; the grammar reads the second word as the site.
+        let record = parse("20240101_120000Metro_Area__TO_52197.mp3").unwrap();
+        assert_eq!(record.system, owned("Metro"));
+        assert_eq!(record.site, owned("Area"));
+        assert_eq!(record.to_id, "52197");
+    }
+
+    #[test]
+    fn extension_is_optional() {
+        assert_eq!(parse("20240101_120000Metro__TO_52197"), parse("20240101_120000Metro__TO_52197.txt"));
+    }
+
+    #[test]
+    fn rejects_other_names() {
+        for name in [
+            "recording.mp3",
+            "20240101_120000.mp3",
+            "2024-01-01_120000Metro__TO_52197.mp3",
+            "20240101_120000Metro__TO_Fire.mp3",
+            "20240101_120000Metro__FROM_1504011.mp3",
+        ] {
+            assert_eq!(parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn custom_pattern() {
+        let pattern: FilenamePattern = r"^(?P<timestamp>\d{8}_\d{6})-tg(?P<to>\d+)(?:-(?P<from>\d+))?$".parse().unwrap();
+        let record = pattern.parse("20240101_120000-tg52197-1504011.mp3").unwrap();
+        assert_eq!(record.timestamp, "20240101_120000");
+        assert_eq!(record.to_id, "52197");
+        assert_eq!(record.from_id, owned("1504011"));
+        assert_eq!(pattern.parse("20240101_120000Metro__TO_52197.mp3"), None);
+    }
+
+    #[test]
+    fn custom_pattern_needs_timestamp_and_to() {
+        let missing_timestamp = r"^(?P<to>\d+)$".parse::<FilenamePattern>().unwrap_err();
+        assert!(missing_timestamp.contains("timestamp"), "{}", missing_timestamp);
+        let missing_to = r"^(?P<timestamp>\d{8}_\d{6})$".parse::<FilenamePattern>().unwrap_err();
+        assert!(missing_to.contains("(?P<to>"), "{}", missing_to);
+        assert!("(unclosed".parse::<FilenamePattern>().is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::dead_letter::unix_now;
+use serde::Serialize;
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Something that happened to a call, as reported by `/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Activity {
+    /// Unix seconds.
+    pub at: u64,
+    pub stem: String,
+    pub sink: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Default)]
+struct Recent {
+    last_success: Option<Activity>,
+    last_error: Option<Activity>,
+    /// Sinks whose most recent upload attempt got no response at all.
+    unreachable: BTreeSet<String>,
+}
+
+/// Liveness and recent activity shared between the pipeline and the HTTP endpoints.
+#[derive(Debug)]
+pub struct Health {
+    started: Instant,
+    /// Unset until the intake loop starts, so a long startup backfill doesn't count as a stall.
+    heartbeat: Mutex<Option<Instant>>,
+    watcher_registered: AtomicBool,
+    recent: Mutex<Recent>,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Health {
+            started: Instant::now(),
+            heartbeat: Mutex::new(None),
+            watcher_registered: AtomicBool::new(false),
+            recent: Mutex::new(Recent::default()),
+        }
+    }
+
+    /// Called on every pass of the intake loop, so a stalled loop shows up as stale.
+    pub fn beat(&self) {
+        *self.heartbeat.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn since_heartbeat(&self) -> Option<Duration> {
+        self.heartbeat.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn set_watcher_registered(&self) {
+        self.watcher_registered.store(true, Ordering::Relaxed);
+    }
+
+    pub fn watcher_registered(&self) -> bool {
+        self.watcher_registered.load(Ordering::Relaxed)
+    }
+
+    pub fn record_success(&self, sink: &str, stem: &str, detail: String) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.last_success = Some(Activity { at: unix_now(), stem: stem.to_string(), sink: sink.to_string(), detail });
+        recent.unreachable.remove(sink);
+    }
+
+    /// `responded` is whether the sink answered at all, even with an error status.
+    pub fn record_error(&self, sink: &str, stem: &str, detail: String, responded: Option<bool>) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.last_error = Some(Activity { at: unix_now(), stem: stem.to_string(), sink: sink.to_string(), detail });
+        match responded {
+            Some(true) => {
+                recent.unreachable.remove(sink);
+            }
+            Some(false) => {
+                recent.unreachable.insert(sink.to_string());
+            }
+            None => {}
+        }
+    }
+
+    pub fn last_success(&self) -> Option<Activity> {
+        self.recent.lock().unwrap().last_success.clone()
+    }
+
+    pub fn last_error(&self) -> Option<Activity> {
+        self.recent.lock().unwrap().last_error.clone()
+    }
+
+    /// Names of the sinks that could not be reached on their latest attempt.
+    pub fn unreachable_sinks(&self) -> Vec<String> {
+        self.recent.lock().unwrap().unreachable.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{config::DEFAULT_SINK, queue::predates_sinks};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{path::Path, sync::Mutex};
+
+/// A call a sink has acknowledged.
+#[derive(Debug, Clone)]
+pub struct LedgerEntry {
+    /// Filename without extension, shared by the mp3 and txt.
+    pub stem: String,
+    pub sink: String,
+    pub mp3_sha256: String,
+    pub txt_sha256: String,
+    pub mp3_path: String,
+    /// Unix seconds.
+    pub uploaded_at: i64,
+    pub status: u16,
+    pub call_id: Option<String>,
+    pub response: String,
+}
+
+/// Persistent record of every uploaded call, keyed on sink and stem plus the SHA-256 of both
+/// files, so restarts and re-touched directories don't send the same call twice.
+pub struct Ledger {
+    conn: Mutex<Connection>,
+}
+
+const COLUMNS: &str = "stem, sink, mp3_sha256, txt_sha256, mp3_path, uploaded_at, status, call_id, response";
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS uploads (
+    stem        TEXT NOT NULL,
+    sink        TEXT NOT NULL,
+    mp3_sha256  TEXT NOT NULL,
+    txt_sha256  TEXT NOT NULL,
+    mp3_path    TEXT NOT NULL,
+    uploaded_at INTEGER NOT NULL,
+    status      INTEGER NOT NULL,
+    call_id     TEXT,
+    response    TEXT NOT NULL,
+    PRIMARY KEY (stem, sink, mp3_sha256, txt_sha256)
+);
+CREATE INDEX IF NOT EXISTS uploads_uploaded_at ON uploads (uploaded_at);";
+
+impl Ledger {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        if predates_sinks(&conn, "uploads")? {
+            // Everything uploaded before sinks existed went to the sink built from `[upload]`.
+            conn.execute_batch(&format!(
+                "BEGIN;
+                 ALTER TABLE uploads RENAME TO uploads_before_sinks;
+                 DROP INDEX IF EXISTS uploads_uploaded_at;
+                 {}
+                 INSERT INTO uploads ({})
+                 SELECT stem, '{}', mp3_sha256, txt_sha256, mp3_path, uploaded_at, status, call_id, response
+                 FROM uploads_before_sinks;
+                 DROP TABLE uploads_before_sinks;
+                 COMMIT;",
+                SCHEMA, COLUMNS, DEFAULT_SINK
+            ))?;
+        }
+        conn.execute_batch(SCHEMA)?;
+        Ok(Ledger { conn: Mutex::new(conn) })
+    }
+
+    pub fn find(
+        &self,
+        sink: &str,
+        stem: &str,
+        mp3_sha256: &str,
+        txt_sha256: &str,
+    ) -> rusqlite::Result<Option<LedgerEntry>> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!(
+                    "SELECT {} FROM uploads WHERE sink = ?1 AND stem = ?2 AND mp3_sha256 = ?3 AND txt_sha256 = ?4",
+                    COLUMNS
+                ),
+                params![sink, stem, mp3_sha256, txt_sha256],
+                entry_from_row,
+            )
+            .optional()
+    }
+
+    pub fn has_stem(&self, stem: &str) -> rusqlite::Result<bool> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT EXISTS (SELECT 1 FROM uploads WHERE stem = ?1)", params![stem], |row| row.get(0))
+    }
+
+    pub fn record(&self, entry: &LedgerEntry) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            &format!("INSERT OR REPLACE INTO uploads ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)", COLUMNS),
+            params![
+                entry.stem,
+                entry.sink,
+                entry.mp3_sha256,
+                entry.txt_sha256,
+                entry.mp3_path,
+                entry.uploaded_at,
+                entry.status,
+                entry.call_id,
+                entry.response
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent entries first, optionally only those whose stem contains `stem` and that
+    /// went to `sink`.
+    pub fn list(&self, stem: Option<&str>, sink: Option<&str>, limit: usize) -> rusqlite::Result<Vec<LedgerEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM uploads WHERE (?1 IS NULL OR instr(stem, ?1) > 0) AND (?2 IS NULL OR sink = ?2)
+             ORDER BY uploaded_at DESC LIMIT ?3",
+            COLUMNS
+        ))?;
+        let entries = statement.query_map(params![stem, sink, limit as i64], entry_from_row)?.collect();
+        entries
+    }
+
+    /// Deletes entries matching every given filter so those calls can be uploaded again.
+    pub fn purge(&self, stem: Option<&str>, sink: Option<&str>, uploaded_before: Option<i64>) -> rusqlite::Result<usize> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM uploads
+             WHERE (?1 IS NULL OR instr(stem, ?1) > 0) AND (?2 IS NULL OR sink = ?2) AND (?3 IS NULL OR uploaded_at < ?3)",
+            params![stem, sink, uploaded_before],
+        )
+    }
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<LedgerEntry> {
+    Ok(LedgerEntry {
+        stem: row.get(0)?,
+        sink: row.get(1)?,
+        mp3_sha256: row.get(2)?,
+        txt_sha256: row.get(3)?,
+        mp3_path: row.get(4)?,
+        uploaded_at: row.get(5)?,
+        status: row.get(6)?,
+        call_id: row.get(7)?,
+        response: row.get(8)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    /// A ledger file in the temp directory, removed with its WAL files when dropped.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let file = format!("uploader-ledger-{}-{}.sqlite3", std::process::id(), name);
+            let db = TempDb(std::env::temp_dir().join(file));
+            db.remove();
+            db
+        }
+
+        fn remove(&self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn entry(stem: &str, sink: &str, uploaded_at: i64) -> LedgerEntry {
+        LedgerEntry {
+            stem: stem.to_string(),
+            sink: sink.to_string(),
+            mp3_sha256: format!("mp3-{}", stem),
+            txt_sha256: format!("txt-{}", stem),
+            mp3_path: format!("/rec/{}.mp3", stem),
+            uploaded_at,
+            status: 200,
+            call_id: None,
+            response: String::new(),
+        }
+    }
+
+    fn stems(entries: Vec<LedgerEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| format!("{}@{}", entry.stem, entry.sink)).collect()
+    }
+
+    #[test]
+    fn find_matches_sink_stem_and_both_hashes() {
+        let db = TempDb::new("find");
+        let ledger = Ledger::open(&db.0).unwrap();
+        let recorded = entry("20240101_120000__TO_1", "api", 100);
+        ledger.record(&recorded).unwrap();
+
+        let (stem, mp3, txt) = (&recorded.stem, &recorded.mp3_sha256, &recorded.txt_sha256);
+        assert!(ledger.find("api", stem, mp3, txt).unwrap().is_some());
+        assert!(ledger.find("mirror", stem, mp3, txt).unwrap().is_none());
+        // A re-transcribed call has a new txt hash and goes out again.
+        assert!(ledger.find("api", stem, mp3, "other").unwrap().is_none());
+        assert!(ledger.find("api", stem, "other", txt).unwrap().is_none());
+        assert!(ledger.has_stem(stem).unwrap());
+        assert!(!ledger.has_stem("20240101_120000__TO_2").unwrap());
+    }
+
+    #[test]
+    fn list_filters_and_orders_newest_first() {
+        let db = TempDb::new("list");
+        let ledger = Ledger::open(&db.0).unwrap();
+        for recorded in [entry("a_TO_1", "api", 100), entry("b_TO_2", "api", 300), entry("a_TO_1", "mirror", 200)] {
+            ledger.record(&recorded).unwrap();
+        }
+
+        assert_eq!(stems(ledger.list(None, None, 10).unwrap()), ["b_TO_2@api", "a_TO_1@mirror", "a_TO_1@api"]);
+        assert_eq!(stems(ledger.list(None, None, 1).unwrap()), ["b_TO_2@api"]);
+        assert_eq!(stems(ledger.list(Some("TO_1"), None, 10).unwrap()), ["a_TO_1@mirror", "a_TO_1@api"]);
+        assert_eq!(stems(ledger.list(Some("TO_1"), Some("api"), 10).unwrap()), ["a_TO_1@api"]);
+    }
+
+    #[test]
+    fn purge_removes_only_what_every_filter_matches() {
+        let db = TempDb::new("purge");
+        let ledger = Ledger::open(&db.0).unwrap();
+        for recorded in [
+            entry("a_TO_1", "api", 100),
+            entry("a_TO_1", "mirror", 100),
+            entry("b_TO_2", "api", 200),
+            entry("c_TO_1", "api", 300),
+        ] {
+            ledger.record(&recorded).unwrap();
+        }
+
+        assert_eq!(ledger.purge(Some("TO_1"), Some("api"), Some(300)).unwrap(), 1);
+        assert_eq!(stems(ledger.list(None, None, 10).unwrap()), ["c_TO_1@api", "b_TO_2@api", "a_TO_1@mirror"]);
+        assert_eq!(ledger.purge(None, Some("mirror"), None).unwrap(), 1);
+        assert_eq!(ledger.purge(None, None, Some(250)).unwrap(), 1);
+        assert_eq!(stems(ledger.list(None, None, 10).unwrap()), ["c_TO_1@api"]);
+        assert_eq!(ledger.purge(None, None, None).unwrap(), 1);
+    }
+
+    #[test]
+    fn migrates_entries_from_before_sinks() {
+        let db = TempDb::new("migrate");
+        Connection::open(&db.0)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE uploads (
+                     stem TEXT NOT NULL, mp3_sha256 TEXT NOT NULL, txt_sha256 TEXT NOT NULL, mp3_path TEXT NOT NULL,
+                     uploaded_at INTEGER NOT NULL, status INTEGER NOT NULL, call_id TEXT, response TEXT NOT NULL,
+                     PRIMARY KEY (stem, mp3_sha256, txt_sha256)
+                 );
+                 CREATE INDEX uploads_uploaded_at ON uploads (uploaded_at);
+                 INSERT INTO uploads
+                 VALUES ('a_TO_1', 'm', 't', '/rec/a_TO_1.mp3', 100, 201, '42', '{\"callId\": 42}');",
+            )
+            .unwrap();
+
+        let ledger = Ledger::open(&db.0).unwrap();
+        let migrated = ledger.find(DEFAULT_SINK, "a_TO_1", "m", "t").unwrap().unwrap();
+        assert_eq!((migrated.status, migrated.call_id.as_deref(), migrated.uploaded_at), (201, Some("42"), 100));
+        // The new schema takes entries for other sinks alongside it.
+        ledger.record(&LedgerEntry { sink: "mirror".to_string(), ..migrated }).unwrap();
+        assert_eq!(ledger.list(Some("a_TO_1"), None, 10).unwrap().len(), 2);
+    }
+}
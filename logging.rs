@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use std::{
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line, including the fields of every enclosing span.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("expected text or json, got {:?}", s)),
+        }
+    }
+}
+
+/// Lets the filter be replaced while the uploader is running.
+#[derive(Clone)]
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogHandle {
+    /// Replaces the active filter with `directives`, e.g. `info,uploader[call{talkgroup=1234}]=debug`.
+    pub fn set_filter(&self, directives: &str) -> Result<(), String> {
+        let filter = parse_filter(directives)?;
+        self.filter.reload(filter).map_err(|e| e.to_string())
+    }
+}
+
+/// Installs the global subscriber, writing to stderr so stdout stays free for command output.
+pub fn init(directives: &str, format: LogFormat) -> Result<LogHandle, String> {
+    let (filter, handle) = reload::Layer::new(parse_filter(directives)?);
+    let registry = tracing_subscriber::registry().with(filter);
+    let result = match format {
+        LogFormat::Text => registry
+            .with(fmt::layer().with_ansi(io::stderr().is_terminal()).with_writer(io::stderr))
+            .try_init(),
+        LogFormat::Json => registry
+            .with(fmt::layer().json().with_current_span(false).with_span_list(true).with_writer(io::stderr))
+            .try_init(),
+    };
+    result.map_err(|e| e.to_string())?;
+    Ok(LogHandle { filter: handle })
+}
+
+/// Parses `EnvFilter` directives: a bare level such as `debug`, or per-target and per-span
+/// rules such as `warn,uploader=info`.
+pub fn parse_filter(directives: &str) -> Result<EnvFilter, String> {
+    EnvFilter::builder().parse(directives).map_err(|e| e.to_string())
+}
@@ -0,0 +1,97 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use reqwest::StatusCode;
+
+/// Prometheus counters for the whole pipeline, from raw watcher events to finished uploads.
+pub struct Metrics {
+    registry: Registry,
+    pub events: IntCounter,
+    /// Paths rejected by `should_process_file`.
+    pub files_filtered: IntCounter,
+    /// Complete mp3/txt pairs handed to the queue, from the watcher or a backfill.
+    pub pairs_found: IntCounter,
+    /// Queued pairs whose filename had no `FROM` radio id.
+    pub calls_without_source: IntCounter,
+    /// Upload attempts by `sink`, `result` (success or failure) and `status_class` (2xx..5xx,
+    /// or `error` when no response was received).
+    pub uploads: IntCounterVec,
+    /// Bytes of audio and transcript in delivered calls, by `sink`, as each sink reports sending
+    /// them; Rdio Scanner, OpenMHz and Broadcastify send only the audio, and SQLite nothing.
+    pub bytes_sent: IntCounterVec,
+    /// Queue entries, one per call and sink.
+    pub queue_depth: IntGauge,
+    pub parse_failures: IntCounter,
+    pub upload_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("uploader".to_string()), None).expect("Invalid metrics prefix");
+        let metrics = Metrics {
+            events: IntCounter::new("watch_events_total", "Filesystem events received from the watcher").unwrap(),
+            files_filtered: IntCounter::new("files_filtered_total", "Changed paths ignored as not call files").unwrap(),
+            pairs_found: IntCounter::new("pairs_found_total", "Complete mp3/txt pairs queued for upload").unwrap(),
+            calls_without_source: IntCounter::new(
+                "calls_without_source_total",
+                "Queued pairs whose filename names no source radio",
+            )
+            .unwrap(),
+            uploads: IntCounterVec::new(
+                Opts::new("uploads_total", "Upload attempts by sink, result and HTTP status class"),
+                &["sink", "result", "status_class"],
+            )
+            .unwrap(),
+            bytes_sent: IntCounterVec::new(
+                Opts::new("upload_bytes_total", "Bytes of audio and transcript sent in delivered calls, by sink"),
+                &["sink"],
+            )
+            .unwrap(),
+            queue_depth: IntGauge::new("queue_depth", "Uploads waiting in the queue, one per call and sink").unwrap(),
+            parse_failures: IntCounter::new("filename_parse_failures_total", "Filenames that could not be parsed")
+                .unwrap(),
+            upload_duration: HistogramVec::new(
+                HistogramOpts::new("upload_duration_seconds", "Time taken by each upload, by sink")
+                    .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]),
+                &["sink"],
+            )
+            .unwrap(),
+            registry,
+        };
+        for collector in [
+            Box::new(metrics.events.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.files_filtered.clone()),
+            Box::new(metrics.pairs_found.clone()),
+            Box::new(metrics.calls_without_source.clone()),
+            Box::new(metrics.uploads.clone()),
+            Box::new(metrics.bytes_sent.clone()),
+            Box::new(metrics.queue_depth.clone()),
+            Box::new(metrics.parse_failures.clone()),
+            Box::new(metrics.upload_duration.clone()),
+        ] {
+            metrics.registry.register(collector).expect("Duplicate metric");
+        }
+        metrics
+    }
+
+    /// Counts one upload attempt to `sink`; `status` is `None` when no response was received.
+    pub fn record_upload(&self, sink: &str, success: bool, status: Option<StatusCode>) {
+        let result = if success { "success" } else { "failure" };
+        let class = match status.map(|s| s.as_u16() / 100) {
+            Some(1) => "1xx",
+            Some(2) => "2xx",
+            Some(3) => "3xx",
+            Some(4) => "4xx",
+            Some(5) => "5xx",
+            _ => "error",
+        };
+        self.uploads.with_label_values(&[sink, result, class]).inc();
+    }
+
+    /// Everything in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).expect("Failed to encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not UTF-8")
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{config::MqttConfig, event::CallEvent, template::EventTemplate};
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS};
+use std::{sync::Mutex, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Events held while the broker is unreachable; further events are dropped until it's back.
+const QUEUED_EVENTS: usize = 256;
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long shutdown waits for queued events to reach the broker.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Publishes call events to the `[mqtt]` broker as JSON. Publishing never holds up uploads:
+/// the connection is kept up in the background, and events that can't be queued are logged
+/// and dropped.
+pub struct Publisher {
+    client: AsyncClient,
+    topic: EventTemplate,
+    qos: QoS,
+    retain: bool,
+    connection: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Publisher {
+    /// Starts connecting to the broker, or returns `None` if `mqtt.broker` isn't set.
+    pub fn start(settings: &MqttConfig) -> Option<Self> {
+        let broker = settings.broker.clone()?;
+        let (host, port) = settings.broker_address()?.ok()?;
+        let mut options = MqttOptions::new(&settings.client_id, host, port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some(username) = &settings.username {
+            options.set_credentials(username, settings.password.clone().unwrap_or_default());
+        }
+        let (client, eventloop) = AsyncClient::new(options, QUEUED_EVENTS);
+        let qos = match settings.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        };
+        Some(Publisher {
+            client,
+            topic: settings.topic.clone(),
+            qos,
+            retain: settings.retain,
+            connection: Mutex::new(Some(tokio::spawn(drive(eventloop, broker)))),
+        })
+    }
+
+    /// Queues `event` for the broker.
+    pub fn publish(&self, event: &CallEvent) {
+        // Field values can't add topic levels or wildcards.
+        let topic = self.topic.render_with(&event.fields, |value| value.replace(['/', '+', '#'], "_"));
+        let payload = serde_json::to_vec(event).expect("call events always serialize");
+        match self.client.try_publish(&topic, self.qos, self.retain, payload) {
+            Ok(()) => debug!(topic, "Published call event"),
+            Err(e) => warn!(topic, "Dropped call event: {}", e),
+        }
+    }
+
+    /// Sends what's queued and disconnects, giving up after `CLOSE_TIMEOUT`.
+    pub async fn close(&self) {
+        let Some(connection) = self.connection.lock().unwrap().take() else {
+            return;
+        };
+        if self.client.try_disconnect().is_ok() && tokio::time::timeout(CLOSE_TIMEOUT, connection).await.is_ok() {
+            return;
+        }
+        warn!("Closed the MQTT connection with call events still unsent");
+    }
+}
+
+/// Runs the connection until it disconnects cleanly, reconnecting after failures. Only
+/// changes between connected and not are logged at warn, so a broker that stays down
+/// doesn't flood the log.
+async fn drive(mut eventloop: EventLoop, broker: String) {
+    let mut connected = None;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!(broker, "Connected to MQTT broker");
+                connected = Some(true);
+            }
+            Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+            Ok(_) => {}
+            Err(e) => {
+                if connected != Some(false) {
+                    warn!(broker, retry_in = ?RECONNECT_DELAY, "MQTT broker unreachable: {}", e);
+                } else {
+                    debug!(broker, "MQTT broker still unreachable: {}", e);
+                }
+                connected = Some(false);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::config::DEFAULT_SINK;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Notify;
+use tracing::info;
+
+/// A paired mp3/txt waiting to be uploaded to one sink.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: i64,
+    pub mp3_path: PathBuf,
+    pub txt_path: PathBuf,
+    pub sink: String,
+    pub attempts: u32,
+}
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS upload_queue (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    mp3_path        TEXT NOT NULL,
+    txt_path        TEXT NOT NULL,
+    sink            TEXT NOT NULL,
+    enqueued_at     INTEGER NOT NULL,
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL,
+    in_flight       INTEGER NOT NULL DEFAULT 0,
+    last_error      TEXT,
+    UNIQUE (mp3_path, sink)
+);";
+
+/// On-disk queue of pending uploads, stored in a SQLite file so that nothing is lost
+/// when the uploader or the API host goes away. Jobs are only removed once `ack` is called.
+pub struct UploadQueue {
+    conn: Mutex<Connection>,
+    ready: Notify,
+}
+
+impl UploadQueue {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        if predates_sinks(&conn, "upload_queue")? {
+            // One row per call becomes one row per call and sink; existing rows belong to the
+            // sink built from `[upload]`.
+            conn.execute_batch(&format!(
+                "BEGIN;
+                 ALTER TABLE upload_queue RENAME TO upload_queue_before_sinks;
+                 {}
+                 INSERT INTO upload_queue
+                     (id, mp3_path, txt_path, sink, enqueued_at, attempts, next_attempt_at, in_flight, last_error)
+                 SELECT id, mp3_path, txt_path, '{}', enqueued_at, attempts, next_attempt_at, in_flight, last_error
+                 FROM upload_queue_before_sinks;
+                 DROP TABLE upload_queue_before_sinks;
+                 COMMIT;",
+                SCHEMA, DEFAULT_SINK
+            ))?;
+            info!("Migrated upload queue to per-sink entries");
+        }
+        conn.execute_batch(SCHEMA)?;
+        Ok(UploadQueue { conn: Mutex::new(conn), ready: Notify::new() })
+    }
+
+    /// Adds a pair to the queue once for each of `sinks`. Returns `false` if the mp3 is
+    /// already queued for all of them.
+    pub fn enqueue(&self, mp3_path: &Path, txt_path: &Path, sinks: &[&str]) -> rusqlite::Result<bool> {
+        let now = now_millis();
+        let mut inserted = 0;
+        let conn = self.conn.lock().unwrap();
+        for sink in sinks {
+            inserted += conn.execute(
+                "INSERT OR IGNORE INTO upload_queue (mp3_path, txt_path, sink, enqueued_at, next_attempt_at)
+                 VALUES (?1, ?2, ?3, ?4, ?4)",
+                params![mp3_path.to_string_lossy(), txt_path.to_string_lossy(), sink, now],
+            )?;
+        }
+        // One wakeup per new row, so idle workers can take the sinks in parallel.
+        for _ in 0..inserted {
+            self.ready.notify_one();
+        }
+        Ok(inserted > 0)
+    }
+
+    /// Takes the oldest job that is due and marks it in flight so no other worker picks it up.
+    pub fn claim(&self) -> rusqlite::Result<Option<Job>> {
+        let conn = self.conn.lock().unwrap();
+        let job = conn
+            .query_row(
+                "SELECT id, mp3_path, txt_path, sink, attempts FROM upload_queue
+                 WHERE in_flight = 0 AND next_attempt_at <= ?1
+                 ORDER BY next_attempt_at, id LIMIT 1",
+                params![now_millis()],
+                |row| {
+                    Ok(Job {
+                        id: row.get(0)?,
+                        mp3_path: PathBuf::from(row.get::<_, String>(1)?),
+                        txt_path: PathBuf::from(row.get::<_, String>(2)?),
+                        sink: row.get(3)?,
+                        attempts: row.get(4)?,
+                    })
+                },
+            )
+            .optional()?;
+        if let Some(job) = &job {
+            conn.execute("UPDATE upload_queue SET in_flight = 1 WHERE id = ?1", params![job.id])?;
+        }
+        Ok(job)
+    }
+
+    /// Removes a job after the server has acknowledged it.
+    pub fn ack(&self, id: i64) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute("DELETE FROM upload_queue WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Puts a failed job back in the queue to be retried after `delay`.
+    pub fn release(&self, id: i64, delay: Duration, error: &str) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE upload_queue
+             SET in_flight = 0, attempts = attempts + 1, next_attempt_at = ?2, last_error = ?3
+             WHERE id = ?1",
+            params![id, now_millis() + delay.as_millis() as i64, error],
+        )?;
+        Ok(())
+    }
+
+    /// Returns every in-flight job to the queue without counting an attempt, for uploads
+    /// that were cut off rather than failed. Returns how many were returned.
+    pub fn requeue_in_flight(&self) -> rusqlite::Result<usize> {
+        self.conn.lock().unwrap().execute("UPDATE upload_queue SET in_flight = 0 WHERE in_flight = 1", [])
+    }
+
+    /// Whether the mp3 is still queued for any sink other than the job `id`.
+    pub fn queued_elsewhere(&self, id: i64, mp3_path: &Path) -> rusqlite::Result<bool> {
+        self.conn.lock().unwrap().query_row(
+            "SELECT EXISTS (SELECT 1 FROM upload_queue WHERE mp3_path = ?1 AND id != ?2)",
+            params![mp3_path.to_string_lossy(), id],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn len(&self) -> rusqlite::Result<usize> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM upload_queue", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+    }
+
+    /// Waits until something is enqueued or `timeout` elapses, whichever comes first.
+    pub async fn wait(&self, timeout: Duration) {
+        let _ = tokio::time::timeout(timeout, self.ready.notified()).await;
+    }
+}
+
+/// Whether `table` exists but has no `sink` column, i.e. was created before sinks existed.
+pub fn predates_sinks(conn: &Connection, table: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) > 0 AND SUM(name = 'sink') = 0 FROM pragma_table_info(?1)",
+        params![table],
+        |row| row.get(0),
+    )
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A queue file in the temp directory, removed with its WAL files when dropped.
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let file = format!("uploader-queue-{}-{}.sqlite3", std::process::id(), name);
+            let db = TempDb(std::env::temp_dir().join(file));
+            db.remove();
+            db
+        }
+
+        fn remove(&self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn pair(stem: &str) -> (PathBuf, PathBuf) {
+        (PathBuf::from(format!("/rec/{}.mp3", stem)), PathBuf::from(format!("/rec/{}.txt", stem)))
+    }
+
+    #[test]
+    fn enqueue_adds_one_entry_per_sink_once() {
+        let db = TempDb::new("enqueue");
+        let queue = UploadQueue::open(&db.0).unwrap();
+        let (mp3, txt) = pair("a");
+        assert!(queue.enqueue(&mp3, &txt, &["api", "mirror"]).unwrap());
+        assert!(!queue.enqueue(&mp3, &txt, &["api", "mirror"]).unwrap());
+        assert_eq!(queue.len().unwrap(), 2);
+        // A sink added later still gets the call.
+        assert!(queue.enqueue(&mp3, &txt, &["api", "mirror", "archive"]).unwrap());
+        assert_eq!(queue.len().unwrap(), 3);
+    }
+
+    #[test]
+    fn claim_hands_out_each_job_once_until_acked() {
+        let db = TempDb::new("claim");
+        let queue = UploadQueue::open(&db.0).unwrap();
+        let (mp3, txt) = pair("a");
+        queue.enqueue(&mp3, &txt, &["api", "mirror"]).unwrap();
+
+        let first = queue.claim().unwrap().unwrap();
+        let second = queue.claim().unwrap().unwrap();
+        assert_ne!(first.sink, second.sink);
+        assert_eq!((&first.mp3_path, &first.txt_path, first.attempts), (&mp3, &txt, 0));
+        assert!(queue.claim().unwrap().is_none());
+
+        assert!(queue.queued_elsewhere(first.id, &mp3).unwrap());
+        queue.ack(second.id).unwrap();
+        assert!(!queue.queued_elsewhere(first.id, &mp3).unwrap());
+        queue.ack(first.id).unwrap();
+        assert_eq!(queue.len().unwrap(), 0);
+    }
+
+    #[test]
+    fn release_counts_the_attempt_and_waits_out_the_delay() {
+        let db = TempDb::new("release");
+        let queue = UploadQueue::open(&db.0).unwrap();
+        let (mp3, txt) = pair("a");
+        queue.enqueue(&mp3, &txt, &["api"]).unwrap();
+
+        let job = queue.claim().unwrap().unwrap();
+        queue.release(job.id, Duration::from_secs(3600), "503 Service Unavailable").unwrap();
+        assert!(queue.claim().unwrap().is_none());
+
+        let (mp3, txt) = pair("b");
+        queue.enqueue(&mp3, &txt, &["api"]).unwrap();
+        let job = queue.claim().unwrap().unwrap();
+        queue.release(job.id, Duration::ZERO, "timed out").unwrap();
+        let retried = queue.claim().unwrap().unwrap();
+        assert_eq!((retried.id, retried.attempts), (job.id, 1));
+    }
+
+    #[test]
+    fn in_flight_jobs_survive_a_restart_until_requeued() {
+        let db = TempDb::new("requeue");
+        let (mp3, txt) = pair("a");
+        let claimed = {
+            let queue = UploadQueue::open(&db.0).unwrap();
+            queue.enqueue(&mp3, &txt, &["api"]).unwrap();
+            queue.claim().unwrap().unwrap()
+        };
+
+        let queue = UploadQueue::open(&db.0).unwrap();
+        assert_eq!(queue.len().unwrap(), 1);
+        assert!(queue.claim().unwrap().is_none());
+        assert_eq!(queue.requeue_in_flight().unwrap(), 1);
+        let job = queue.claim().unwrap().unwrap();
+        // An interrupted upload isn't a failed attempt.
+        assert_eq!((job.id, job.attempts), (claimed.id, 0));
+    }
+
+    #[test]
+    fn migrates_entries_from_before_sinks() {
+        let db = TempDb::new("migrate");
+        Connection::open(&db.0)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE upload_queue (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT, mp3_path TEXT NOT NULL UNIQUE, txt_path TEXT NOT NULL,
+                     enqueued_at INTEGER NOT NULL, attempts INTEGER NOT NULL DEFAULT 0,
+                     next_attempt_at INTEGER NOT NULL, in_flight INTEGER NOT NULL DEFAULT 0, last_error TEXT
+                 );
+                 INSERT INTO upload_queue (mp3_path, txt_path, enqueued_at, attempts, next_attempt_at)
+                 VALUES ('/rec/a.mp3', '/rec/a.txt', 0, 2, 0);",
+            )
+            .unwrap();
+
+        let queue = UploadQueue::open(&db.0).unwrap();
+        let job = queue.claim().unwrap().unwrap();
+        assert_eq!((job.sink.as_str(), job.attempts), (DEFAULT_SINK, 2));
+        assert_eq!(job.mp3_path, PathBuf::from("/rec/a.mp3"));
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// The file has stopped changing and can be uploaded.
+    Ready,
+    /// The file is still being written, or hasn't been quiet for long enough yet.
+    Waiting,
+    /// The file no longer exists.
+    Missing,
+}
+
+#[derive(Debug)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+    unchanged_since: Instant,
+    closed: bool,
+    /// Last time the file was closed or checked.
+    seen: Instant,
+}
+
+/// Decides when a file has finished being written: either a close-after-write event was
+/// seen and the file hasn't changed since, or its size and mtime have held still for `settle`.
+#[derive(Debug)]
+pub struct ReadinessTracker {
+    settle: Duration,
+    files: HashMap<PathBuf, FileState>,
+}
+
+impl ReadinessTracker {
+    pub fn new(settle: Duration) -> Self {
+        ReadinessTracker { settle, files: HashMap::new() }
+    }
+
+    /// Records that the writer closed `path`, so it is ready as long as it stays as it is now.
+    pub fn closed_for_write(&mut self, path: &Path) {
+        if let Ok(metadata) = fs::metadata(path) {
+            self.files.insert(
+                path.to_path_buf(),
+                FileState {
+                    len: metadata.len(),
+                    modified: metadata.modified().ok(),
+                    unchanged_since: Instant::now(),
+                    closed: true,
+                    seen: Instant::now(),
+                },
+            );
+        }
+    }
+
+    pub fn check(&mut self, path: &Path) -> Readiness {
+        let Ok(metadata) = fs::metadata(path) else {
+            self.files.remove(path);
+            return Readiness::Missing;
+        };
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let now = Instant::now();
+        match self.files.get_mut(path) {
+            Some(state) if state.len == len && state.modified == modified => {
+                state.seen = now;
+                if state.closed || now.duration_since(state.unchanged_since) >= self.settle {
+                    Readiness::Ready
+                } else {
+                    Readiness::Waiting
+                }
+            }
+            Some(state) => {
+                *state = FileState { len, modified, unchanged_since: now, closed: false, seen: now };
+                Readiness::Waiting
+            }
+            None => {
+                let state = FileState { len, modified, unchanged_since: now, closed: false, seen: now };
+                self.files.insert(path.to_path_buf(), state);
+                if self.settle.is_zero() {
+                    Readiness::Ready
+                } else {
+                    Readiness::Waiting
+                }
+            }
+        }
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Drops files that haven't been closed or checked for `idle`, such as an mp3 whose
+    /// transcript never appeared.
+    pub fn prune(&mut self, idle: Duration) {
+        let now = Instant::now();
+        self.files.retain(|_, state| now.duration_since(state.seen) < idle);
+    }
+}
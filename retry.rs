@@ -0,0 +1,74 @@
+use rand::Rng;
+use std::time::Duration;
+
+/// How failed uploads are retried: exponential backoff from `base_delay`, capped at
+/// `max_delay`, with up to `jitter` (a fraction of the delay) randomly shaved off so a
+/// recovering server isn't hit by every queued call at the same instant.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Delay before the next try after `attempt` attempts have failed, or `None` once the
+    /// policy is exhausted. A server-provided `Retry-After` is honored if it is longer.
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let exponent = attempt.saturating_sub(1).min(31);
+        let backoff = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        let shaved = backoff.mul_f64(self.jitter * rand::thread_rng().gen::<f64>());
+        let delay = backoff - shaved;
+        Some(retry_after.map_or(delay, |server| server.max(delay)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy { max_attempts: 10, base_delay: Duration::from_secs(5), max_delay: Duration::from_secs(60), jitter }
+    }
+
+    fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let delays: Vec<_> = (1..=6).map(|attempt| policy(0.0).delay_for(attempt, None)).collect();
+        assert_eq!(delays, [secs(5), secs(10), secs(20), secs(40), secs(60), secs(60)]);
+        // Large attempt counts don't overflow.
+        let policy = RetryPolicy { max_attempts: u32::MAX, ..policy(0.0) };
+        assert_eq!(policy.delay_for(1000, None), secs(60));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        assert_eq!(policy(0.0).delay_for(9, None), secs(60));
+        assert_eq!(policy(0.0).delay_for(10, None), None);
+        assert_eq!(policy(0.0).delay_for(11, secs(1)), None);
+        assert_eq!(RetryPolicy { max_attempts: 1, ..policy(0.0) }.delay_for(1, None), None);
+    }
+
+    #[test]
+    fn longer_retry_after_wins() {
+        assert_eq!(policy(0.0).delay_for(1, secs(30)), secs(30));
+        assert_eq!(policy(0.0).delay_for(3, secs(1)), secs(20));
+        // Retry-After may exceed max_delay; the server knows best.
+        assert_eq!(policy(0.0).delay_for(1, secs(300)), secs(300));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        for _ in 0..100 {
+            let delay = policy(0.5).delay_for(2, None).unwrap();
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10), "{:?}", delay);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    config::Config,
+    health::{Activity, Health},
+    metrics::Metrics,
+    queue::UploadQueue,
+};
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+use std::{io, net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::{net::TcpListener, sync::watch};
+use tracing::info;
+
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    queue: Arc<UploadQueue>,
+}
+
+/// Serves `/metrics`, `/healthz`, `/readyz` and `/status` on `address` until `shutdown` is signalled.
+pub async fn serve(
+    address: SocketAddr,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    queue: Arc<UploadQueue>,
+    mut shutdown: watch::Receiver<bool>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!(address = %listener.local_addr()?, "Serving metrics and health checks");
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status))
+        .with_state(AppState { config, metrics, health, queue });
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+        })
+        .await
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    if let Ok(depth) = state.queue.len() {
+        state.metrics.queue_depth.set(depth as i64);
+    }
+    ([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], state.metrics.render())
+}
+
+/// Alive unless the intake loop has stopped turning over.
+async fn healthz(State(state): State<AppState>) -> (StatusCode, String) {
+    match state.health.since_heartbeat() {
+        Some(since) if since > state.config.http.stall() => {
+            (StatusCode::SERVICE_UNAVAILABLE, format!("intake loop stalled for {:?}\n", since))
+        }
+        _ => (StatusCode::OK, "ok\n".to_string()),
+    }
+}
+
+async fn readyz(State(state): State<AppState>) -> (StatusCode, String) {
+    let problems = not_ready(&state);
+    if problems.is_empty() {
+        (StatusCode::OK, "ready\n".to_string())
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, problems.join("\n") + "\n")
+    }
+}
+
+fn not_ready(state: &AppState) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !state.health.watcher_registered() {
+        problems.push("watcher not registered".to_string());
+    }
+    for sink in state.health.unreachable_sinks() {
+        problems.push(format!("sink {} unreachable", sink));
+    }
+    match state.queue.len() {
+        Ok(depth) if depth > state.config.http.max_queue_depth => {
+            problems.push(format!("queue holds {} calls, more than {}", depth, state.config.http.max_queue_depth))
+        }
+        Ok(_) => {}
+        Err(e) => problems.push(format!("queue unreadable: {}", e)),
+    }
+    problems
+}
+
+#[derive(Serialize)]
+struct Status {
+    ready: bool,
+    problems: Vec<String>,
+    uptime_secs: u64,
+    watched_roots: Vec<PathBuf>,
+    queue_size: Option<usize>,
+    last_success: Option<Activity>,
+    last_error: Option<Activity>,
+}
+
+async fn status(State(state): State<AppState>) -> Json<Status> {
+    let problems = not_ready(&state);
+    Json(Status {
+        ready: problems.is_empty(),
+        problems,
+        uptime_secs: state.health.uptime().as_secs(),
+        watched_roots: state.config.watch.root_paths(),
+        queue_size: state.queue.len().ok(),
+        last_success: state.health.last_success(),
+        last_error: state.health.last_error(),
+    })
+}
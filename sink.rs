@@ -0,0 +1,398 @@
+mod broadcastify;
+mod multipart;
+mod openmhz;
+mod rdio_scanner;
+mod s3;
+mod sqlite;
+
+use crate::{
+    audio,
+    config::{Config, RootConfig, SinkKind},
+    filename::CallRecord,
+    timestamp::{self, SourceTimezone},
+};
+use async_trait::async_trait;
+use broadcastify::BroadcastifySink;
+use multipart::MultipartSink;
+use openmhz::OpenMhzSink;
+use rdio_scanner::RdioScannerSink;
+use s3::S3Sink;
+use sqlite::SqliteSink;
+use reqwest::{header::RETRY_AFTER, Client, Response, StatusCode};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+/// Longest response body echoed to the log or kept with an error.
+const MAX_LOGGED_BODY: usize = 512;
+
+/// A destination calls are delivered to. Every configured sink gets its own queue entry for
+/// each call, so each is retried, dead-lettered and recorded in the ledger independently.
+#[async_trait]
+pub trait UploadSink: Send + Sync {
+    /// The sink's `name` from the config, used in the queue, ledger, logs and metrics.
+    fn name(&self) -> &str;
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError>;
+}
+
+/// HTTP clients for the sinks to share.
+pub struct Clients {
+    /// Set up from `[tls]`, for the `[upload]` server and other multipart endpoints.
+    pub upload: Client,
+    /// The public roots and `tls.ca_bundle` only, for other services.
+    pub public: Client,
+}
+
+/// Builds every sink in `config.sinks`, in order, opening any local databases they write to.
+pub fn build(config: &Arc<Config>, clients: &Clients) -> rusqlite::Result<Vec<Box<dyn UploadSink>>> {
+    let mut sinks: Vec<Box<dyn UploadSink>> = Vec::new();
+    for sink in &config.sinks {
+        let (name, public, config) = (sink.name.clone(), clients.public.clone(), config.clone());
+        sinks.push(match &sink.kind {
+            SinkKind::Multipart(settings) => {
+                Box::new(MultipartSink::new(name, settings.clone(), clients.upload.clone(), config))
+            }
+            SinkKind::RdioScanner(settings) => Box::new(RdioScannerSink::new(name, settings.clone(), public, config)),
+            SinkKind::OpenMhz(settings) => Box::new(OpenMhzSink::new(name, settings.clone(), public, config)),
+            SinkKind::Broadcastify(settings) => Box::new(BroadcastifySink::new(name, settings.clone(), public, config)),
+            SinkKind::S3(settings) => Box::new(S3Sink::new(name, settings.clone(), public, config)),
+            SinkKind::Sqlite(settings) => Box::new(SqliteSink::open(name, settings.clone(), config)?),
+        });
+    }
+    Ok(sinks)
+}
+
+/// A queued call with its filename parsed, as handed to a sink.
+pub struct Call<'a> {
+    pub record: CallRecord,
+    /// The watch root the call was found under.
+    pub root: &'a RootConfig,
+    pub files: &'a CallFiles,
+}
+
+impl Call<'_> {
+    /// The watch root's system label, or else the one in the filename.
+    pub fn system(&self) -> Option<String> {
+        self.root.system.clone().or_else(|| self.record.system.clone())
+    }
+
+    /// Values for the placeholders in `template::FIELDS`. The date parts are the recorder's
+    /// wall-clock time as written in the filename; `timestamp` and `unix` are the same moment
+    /// in UTC, read in `zone`.
+    pub fn fields(&self, zone: SourceTimezone) -> Result<HashMap<&'static str, String>, UploadError> {
+        let record = &self.record;
+        let invalid = || UploadError::InvalidTimestamp(record.timestamp.clone());
+        let wall_clock = timestamp::parse_wall_clock(&record.timestamp).ok_or_else(invalid)?;
+        let time = timestamp::parse_timestamp(&record.timestamp, zone).ok_or_else(invalid)?;
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let duration = audio::mp3_duration(&self.files.mp3).map(|d| format!("{:.2}", d.as_secs_f64()));
+        Ok(HashMap::from([
+            ("stem", self.files.stem()),
+            ("system", text(&self.system())),
+            ("site", text(&record.site)),
+            ("channel", text(&record.channel)),
+            ("talkgroup", record.to_id.clone()),
+            ("talkgroup_alias", text(&record.to_alias)),
+            ("radio", text(&record.from_id)),
+            ("radio_alias", text(&record.from_alias)),
+            ("protocol", text(&record.protocol)),
+            ("yyyy", wall_clock.format("%Y").to_string()),
+            ("mm", wall_clock.format("%m").to_string()),
+            ("dd", wall_clock.format("%d").to_string()),
+            ("hh", wall_clock.format("%H").to_string()),
+            ("timestamp", time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            ("unix", time.timestamp().to_string()),
+            ("duration", duration.unwrap_or_default()),
+        ]))
+    }
+}
+
+/// A paired call read into memory, so it is hashed and uploaded from the same bytes.
+pub struct CallFiles {
+    pub mp3_path: PathBuf,
+    pub txt_path: PathBuf,
+    pub mp3: Vec<u8>,
+    pub txt: Vec<u8>,
+}
+
+impl CallFiles {
+    pub async fn read(mp3_path: &Path, txt_path: &Path) -> io::Result<Self> {
+        Ok(CallFiles {
+            mp3_path: mp3_path.to_path_buf(),
+            txt_path: txt_path.to_path_buf(),
+            mp3: tokio::fs::read(mp3_path).await?,
+            txt: tokio::fs::read(txt_path).await?,
+        })
+    }
+
+    /// Filename without extension, shared by the mp3 and txt.
+    pub fn stem(&self) -> String {
+        self.mp3_path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    Io(io::Error),
+    Http(reqwest::Error),
+    Status { status: StatusCode, retry_after: Option<Duration>, body: String },
+    UnrecognizedFilename(String),
+    InvalidTimestamp(String),
+    /// The sink's settings don't cover this call.
+    Misconfigured(String),
+    /// The destination answered, but turned the call down for good.
+    Rejected(String),
+    /// A local store failed, e.g. a full disk or a locked database.
+    Storage(String),
+}
+
+impl UploadError {
+    /// Whether trying the same upload again later could succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            UploadError::Io(e) => e.kind() != io::ErrorKind::NotFound,
+            UploadError::Storage(_) => true,
+            UploadError::Http(e) => !e.is_builder(),
+            UploadError::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            UploadError::UnrecognizedFilename(_)
+            | UploadError::InvalidTimestamp(_)
+            | UploadError::Misconfigured(_)
+            | UploadError::Rejected(_) => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            UploadError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            UploadError::Status { status, .. } => Some(*status),
+            UploadError::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Whether the destination answered at all, even with an error status. `None` when the
+    /// failure happened before anything was sent.
+    pub fn responded(&self) -> Option<bool> {
+        match self {
+            UploadError::Status { .. } | UploadError::Rejected(_) => Some(true),
+            UploadError::Http(_) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UploadError::Io(e) => write!(f, "failed to read file: {}", e),
+            UploadError::Http(e) => {
+                write!(f, "request failed: {}", e)?;
+                let mut source = std::error::Error::source(e);
+                while let Some(cause) = source {
+                    write!(f, ": {}", cause)?;
+                    source = cause.source();
+                }
+                Ok(())
+            }
+            UploadError::Status { status, body, .. } if body.is_empty() => write!(f, "server responded {}", status),
+            UploadError::Status { status, body, .. } => write!(f, "server responded {}: {}", status, body),
+            UploadError::UnrecognizedFilename(name) => write!(f, "unrecognized filename: {}", name),
+            UploadError::InvalidTimestamp(raw) => write!(f, "invalid timestamp in filename: {}", raw),
+            UploadError::Misconfigured(reason) => write!(f, "sink misconfigured: {}", reason),
+            UploadError::Rejected(reason) => write!(f, "rejected: {}", reason),
+            UploadError::Storage(e) => write!(f, "storage failed: {}", e),
+        }
+    }
+}
+
+/// What the destination said about a successful upload.
+#[derive(Debug, Clone)]
+pub struct UploadReceipt {
+    pub status: StatusCode,
+    /// Id the server assigned to the call, if its reply was JSON carrying one.
+    pub call_id: Option<String>,
+    /// Response body, truncated for logging and the ledger.
+    pub body: String,
+    /// Bytes of audio and transcript the sink actually sent; nothing for a local store.
+    pub bytes_sent: u64,
+}
+
+/// Reads the reply to a single-request upload of `bytes_sent` bytes of call data into a
+/// receipt, or the error for a failed request.
+async fn receive(sent: reqwest::Result<Response>, bytes_sent: usize) -> Result<UploadReceipt, UploadError> {
+    match sent {
+        Ok(response) if response.status().is_success() => {
+            let status = response.status();
+            let body = read_body(response).await;
+            debug!(status = status.as_u16(), body, "Upload response");
+            Ok(UploadReceipt { status, call_id: None, body, bytes_sent: bytes_sent as u64 })
+        }
+        Ok(response) => Err(status_error(response).await),
+        Err(e) => Err(UploadError::Http(e)),
+    }
+}
+
+/// Turns a non-success response into an error, keeping any `Retry-After` delay.
+pub async fn status_error(response: Response) -> UploadError {
+    // Only the delay-seconds form of Retry-After is understood; HTTP dates fall back to backoff.
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .map(Duration::from_secs);
+    let status = response.status();
+    UploadError::Status { status, retry_after, body: read_body(response).await }
+}
+
+/// Reads a response body, truncated to `MAX_LOGGED_BODY`.
+pub async fn read_body(response: Response) -> String {
+    let body = response.text().await.unwrap_or_else(|e| {
+        warn!("Failed to read response body: {}", e);
+        String::new()
+    });
+    truncate(&body, MAX_LOGGED_BODY).to_string()
+}
+
+/// Pulls the call id out of a JSON acknowledgement such as `{"callId": 1234}`.
+pub fn parse_call_id(body: &str, id_fields: &[String]) -> Option<String> {
+    let ack: serde_json::Value = serde_json::from_str(body).ok()?;
+    id_fields.iter().find_map(|key| match ack.get(key)? {
+        serde_json::Value::String(id) => Some(id.clone()),
+        serde_json::Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    })
+}
+
+fn truncate(text: &str, max: usize) -> &str {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::RootConfig;
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, Method, Uri},
+        Router,
+    };
+    use std::sync::Mutex;
+
+    /// A request the mock server received.
+    #[derive(Debug)]
+    pub(crate) struct Received {
+        pub method: Method,
+        pub path: String,
+        pub headers: HeaderMap,
+        pub body: Vec<u8>,
+    }
+
+    impl Received {
+        pub fn text(&self) -> String {
+            String::from_utf8_lossy(&self.body).into_owned()
+        }
+    }
+
+    type MockState = (Arc<Vec<(StatusCode, String)>>, Arc<Mutex<Vec<Received>>>);
+
+    async fn answer(
+        State((replies, received)): State<MockState>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> (StatusCode, String) {
+        let mut received = received.lock().unwrap();
+        received.push(Received { method, path: uri.path().to_string(), headers, body: body.to_vec() });
+        replies.get(received.len() - 1).cloned().unwrap_or((StatusCode::NOT_FOUND, String::new()))
+    }
+
+    /// Starts a local HTTP server that answers successive requests with `replies`, where
+    /// `{base}` in a reply stands for the server's own URL. Returns that URL and a log of the
+    /// requests it received.
+    pub(crate) async fn mock_server(replies: &[(u16, &str)]) -> (String, Arc<Mutex<Vec<Received>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let replies = replies
+            .iter()
+            .map(|(status, body)| (StatusCode::from_u16(*status).unwrap(), body.replace("{base}", &base)))
+            .collect();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new().fallback(answer).with_state((Arc::new(replies), received.clone()));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (base, received)
+    }
+
+    /// Settings for sink tests: filename timestamps read as UTC.
+    pub(super) fn test_config() -> Arc<Config> {
+        let mut config = Config::default();
+        config.timestamp.timezone = SourceTimezone::Named(chrono_tz::UTC);
+        Arc::new(config)
+    }
+
+    pub(super) fn test_root() -> RootConfig {
+        RootConfig { path: PathBuf::from("/rec"), system: Some("Metro".to_string()), ..RootConfig::default() }
+    }
+
+    /// A call to talkgroup 52197 from radio 1504011 at 2024-01-01 12:00:00.
+    pub(super) fn test_files() -> CallFiles {
+        let stem = "/rec/tg/20240101_120000Metro__TO_52197_FROM_1504011";
+        CallFiles {
+            mp3_path: PathBuf::from(format!("{}.mp3", stem)),
+            txt_path: PathBuf::from(format!("{}.txt", stem)),
+            mp3: b"not really an mp3".to_vec(),
+            txt: b"Engine 1 responding".to_vec(),
+        }
+    }
+
+    pub(super) fn test_call<'a>(root: &'a RootConfig, files: &'a CallFiles) -> Call<'a> {
+        let filename = files.mp3_path.file_name().unwrap().to_string_lossy();
+        Call { record: root.parse_filename(&filename).unwrap(), root, files }
+    }
+
+    fn status(code: u16) -> UploadError {
+        UploadError::Status { status: StatusCode::from_u16(code).unwrap(), retry_after: None, body: String::new() }
+    }
+
+    #[test]
+    fn retries_server_errors_timeouts_and_rate_limits() {
+        for code in [408, 429, 500, 502, 503, 504] {
+            assert!(status(code).is_retryable(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn client_errors_are_permanent() {
+        for code in [400, 401, 403, 404, 413, 422] {
+            assert!(!status(code).is_retryable(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn local_failures() {
+        assert!(UploadError::Io(io::Error::from(io::ErrorKind::PermissionDenied)).is_retryable());
+        assert!(!UploadError::Io(io::Error::from(io::ErrorKind::NotFound)).is_retryable());
+        assert!(UploadError::Storage("database is locked".to_string()).is_retryable());
+        assert!(!UploadError::Rejected("1 Invalid API key".to_string()).is_retryable());
+        assert!(!UploadError::InvalidTimestamp("20240230_120000".to_string()).is_retryable());
+    }
+}
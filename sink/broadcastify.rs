@@ -0,0 +1,157 @@
+use super::{read_body, status_error, Call, UploadError, UploadReceipt, UploadSink};
+use crate::{
+    audio,
+    config::{BroadcastifySinkConfig, Config},
+    timestamp,
+};
+use async_trait::async_trait;
+use reqwest::{header::CONTENT_TYPE, Client};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Reply to a slot request for a call Broadcastify already has from another uploader.
+const ALREADY_RECEIVED: &str = "1 SKIPPED";
+
+/// Broadcastify Calls' two-step upload: the call's details are posted with the API key to
+/// request a slot, and the reply (`0 <url>`, or `1 <reason>`) names a URL the audio is then
+/// PUT to. A retry requests a fresh slot.
+pub struct BroadcastifySink {
+    name: String,
+    settings: BroadcastifySinkConfig,
+    client: Client,
+    config: Arc<Config>,
+}
+
+impl BroadcastifySink {
+    pub fn new(name: String, settings: BroadcastifySinkConfig, client: Client, config: Arc<Config>) -> Self {
+        BroadcastifySink { name, settings, client, config }
+    }
+}
+
+#[async_trait]
+impl UploadSink for BroadcastifySink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError> {
+        let (record, root, files) = (&call.record, call.root, call.files);
+        let system = self.settings.system_id(root).ok_or_else(|| {
+            UploadError::Misconfigured(format!("no Broadcastify Calls system for {}", root.path.display()))
+        })?;
+        let start = timestamp::parse_timestamp(&record.timestamp, self.config.timestamp.timezone)
+            .ok_or_else(|| UploadError::InvalidTimestamp(record.timestamp.clone()))?
+            .timestamp();
+        let length = audio::mp3_duration(&files.mp3).unwrap_or_default();
+        debug!(call = ?record, system, length = ?length, "Requesting Broadcastify Calls upload slot");
+
+        let form = [
+            ("apiKey", self.settings.api_key.clone()),
+            ("systemId", system.to_string()),
+            ("callDuration", format!("{:.2}", length.as_secs_f64())),
+            ("ts", start.to_string()),
+            ("tg", record.to_id.clone()),
+            ("src", record.from_id.clone().unwrap_or_default()),
+            ("freq", "0".to_string()),
+            ("enc", "mp3".to_string()),
+        ];
+        let response = self.client.post(&self.settings.url).form(&form).send().await.map_err(UploadError::Http)?;
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+        let status = response.status();
+        let reply = read_body(response).await;
+        let reply = reply.trim();
+        if reply.starts_with(ALREADY_RECEIVED) {
+            debug!(reply, "Broadcastify Calls already has this call");
+            return Ok(UploadReceipt { status, call_id: None, body: reply.to_string(), bytes_sent: 0 });
+        }
+        let upload_url = match reply.split_once(' ') {
+            Some(("0", url)) => url.trim(),
+            _ => return Err(UploadError::Rejected(reply.to_string())),
+        };
+
+        debug!(upload_url, "Uploading audio to Broadcastify Calls");
+        let response = self
+            .client
+            .put(upload_url)
+            .header(CONTENT_TYPE, "audio/mpeg")
+            .body(files.mp3.clone())
+            .send()
+            .await
+            .map_err(UploadError::Http)?;
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+        let status = response.status();
+        let body = read_body(response).await;
+        debug!(status = status.as_u16(), body, "Broadcastify Calls audio upload response");
+        Ok(UploadReceipt { status, call_id: None, body, bytes_sent: files.mp3.len() as u64 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::tests::{mock_server, test_call, test_config, test_files, test_root};
+    use reqwest::StatusCode;
+
+    fn sink(url: &str) -> BroadcastifySink {
+        let settings = BroadcastifySinkConfig {
+            url: format!("{}/call-upload", url),
+            api_key: "key".to_string(),
+            system: Some(1234),
+            ..BroadcastifySinkConfig::default()
+        };
+        BroadcastifySink::new("bcfy".to_string(), settings, Client::new(), test_config())
+    }
+
+    #[tokio::test]
+    async fn requests_a_slot_then_puts_the_audio() {
+        let (url, received) = mock_server(&[(200, "0 {base}/slot/abc\n"), (200, "")]).await;
+        let (root, files) = (test_root(), test_files());
+        let receipt = sink(&url).upload(&test_call(&root, &files)).await.unwrap();
+        assert_eq!(receipt.bytes_sent, files.mp3.len() as u64);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (slot, put) = (&received[0], &received[1]);
+        assert_eq!((slot.method.as_str(), slot.path.as_str()), ("POST", "/call-upload"));
+        let form = slot.text();
+        let fields: Vec<_> = form.split('&').collect();
+        for field in ["apiKey=key", "systemId=1234", "ts=1704110400", "tg=52197", "src=1504011", "enc=mp3"] {
+            assert!(fields.contains(&field), "{} not in {}", field, form);
+        }
+        assert_eq!((put.method.as_str(), put.path.as_str()), ("PUT", "/slot/abc"));
+        assert_eq!(put.headers[CONTENT_TYPE], "audio/mpeg");
+        assert_eq!(put.body, files.mp3);
+    }
+
+    #[tokio::test]
+    async fn a_call_broadcastify_already_has_is_delivered_without_a_put() {
+        let (url, received) = mock_server(&[(200, "1 SKIPPED duplicate call")]).await;
+        let (root, files) = (test_root(), test_files());
+        let receipt = sink(&url).upload(&test_call(&root, &files)).await.unwrap();
+        assert_eq!((receipt.body.as_str(), receipt.bytes_sent), ("1 SKIPPED duplicate call", 0));
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_refused_slot_is_rejected_for_good() {
+        let (url, received) = mock_server(&[(200, "1 Invalid API Key")]).await;
+        let (root, files) = (test_root(), test_files());
+        let error = sink(&url).upload(&test_call(&root, &files)).await.unwrap_err();
+        assert!(matches!(&error, UploadError::Rejected(reason) if reason == "1 Invalid API Key"), "{:?}", error);
+        assert!(!error.is_retryable());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_failed_put_is_retried() {
+        let (url, _) = mock_server(&[(200, "0 {base}/slot/abc"), (503, "busy")]).await;
+        let (root, files) = (test_root(), test_files());
+        let error = sink(&url).upload(&test_call(&root, &files)).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(error.is_retryable());
+    }
+}
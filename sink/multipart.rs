@@ -0,0 +1,67 @@
+use super::{parse_call_id, receive, Call, UploadError, UploadReceipt, UploadSink};
+use crate::{config::{Config, MultipartSinkConfig}, timestamp};
+use async_trait::async_trait;
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use std::sync::Arc;
+use tracing::debug;
+
+/// POSTs the mp3 and transcript as a multipart form, with the field names from
+/// `[upload.fields]`. The sink's own endpoint and key take precedence over the watch root's,
+/// which take precedence over `[upload]`.
+pub struct MultipartSink {
+    name: String,
+    settings: MultipartSinkConfig,
+    client: Client,
+    config: Arc<Config>,
+}
+
+impl MultipartSink {
+    pub fn new(name: String, settings: MultipartSinkConfig, client: Client, config: Arc<Config>) -> Self {
+        MultipartSink { name, settings, client, config }
+    }
+}
+
+#[async_trait]
+impl UploadSink for MultipartSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError> {
+        let (upload, root, files) = (&self.config.upload, call.root, call.files);
+        let record = &call.record;
+        let timestamp = timestamp::format_timestamp(&record.timestamp, self.config.timestamp.timezone, self.config.timestamp.format)
+            .ok_or_else(|| UploadError::InvalidTimestamp(record.timestamp.clone()))?;
+        debug!(call = ?record, "Uploading");
+        let filename = files.mp3_path.file_name().unwrap().to_string_lossy().into_owned();
+        let mp3_part = Part::bytes(files.mp3.clone()).file_name(filename).mime_str("audio/mpeg").expect("Invalid MIME type");
+        let txt_filename = files.txt_path.file_name().unwrap().to_string_lossy().into_owned();
+        let txt_part = Part::bytes(files.txt.clone()).file_name(txt_filename).mime_str("text/plain").expect("Invalid MIME type");
+
+        let fields = &upload.fields;
+        let mut form = Form::new()
+            .text(fields.talkgroup_id.clone(), record.to_id.clone())
+            .text(fields.timestamp.clone(), timestamp);
+        if let Some(radio_id) = record.from_id.clone().or_else(|| upload.unknown_radio_id.value()) {
+            form = form.text(fields.radio_id.clone(), radio_id);
+        }
+        if let Some(system) = &root.system {
+            form = form.text(fields.system.clone(), system.clone());
+        }
+        let form = form
+            .part(fields.mp3.clone(), mp3_part)
+            .part(fields.transcription.clone(), txt_part);
+
+        let endpoint = self.settings.endpoint.as_deref().unwrap_or_else(|| root.endpoint(upload));
+        let mut request = self.client.post(endpoint).multipart(form);
+        if let Some(api_key) = self.settings.api_key.as_deref().or_else(|| root.api_key(upload)) {
+            let header = self.settings.api_key_header.as_deref().unwrap_or_else(|| root.api_key_header(upload));
+            request = request.header(header, api_key);
+        }
+        let receipt = receive(request.send().await, files.mp3.len() + files.txt.len()).await?;
+        Ok(UploadReceipt { call_id: parse_call_id(&receipt.body, &upload.ack_id_fields), ..receipt })
+    }
+}
@@ -0,0 +1,141 @@
+use super::{receive, Call, UploadError, UploadReceipt, UploadSink};
+use crate::{
+    audio,
+    config::{Config, OpenMhzSinkConfig},
+    timestamp,
+};
+use async_trait::async_trait;
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Posts calls to OpenMHz the way trunk-recorder's uploader does: one multipart request per
+/// call to `/<short name>/upload`, authenticated by an `api_key` field. SDRTrunk doesn't
+/// record the frequency or signal quality, so those go as zero.
+pub struct OpenMhzSink {
+    name: String,
+    settings: OpenMhzSinkConfig,
+    client: Client,
+    config: Arc<Config>,
+}
+
+impl OpenMhzSink {
+    pub fn new(name: String, settings: OpenMhzSinkConfig, client: Client, config: Arc<Config>) -> Self {
+        OpenMhzSink { name, settings, client, config }
+    }
+}
+
+#[async_trait]
+impl UploadSink for OpenMhzSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError> {
+        let (record, root, files) = (&call.record, call.root, call.files);
+        let short_name = self.settings.short_name(root).ok_or_else(|| {
+            UploadError::Misconfigured(format!("no OpenMHz system for {}", root.path.display()))
+        })?;
+        let start = timestamp::parse_timestamp(&record.timestamp, self.config.timestamp.timezone)
+            .ok_or_else(|| UploadError::InvalidTimestamp(record.timestamp.clone()))?
+            .timestamp();
+        let length = audio::mp3_duration(&files.mp3).unwrap_or_default();
+        debug!(call = ?record, short_name, length = ?length, "Uploading to OpenMHz");
+
+        let sources = match &record.from_id {
+            Some(source) => serde_json::json!([{ "pos": 0, "src": source.parse::<u64>().unwrap_or_default() }]),
+            None => serde_json::json!([]),
+        };
+        let filename = files.mp3_path.file_name().unwrap().to_string_lossy().into_owned();
+        let audio = Part::bytes(files.mp3.clone()).file_name(filename).mime_str("audio/mpeg").expect("Invalid MIME type");
+        let form = Form::new()
+            .part("call", audio)
+            .text("api_key", self.settings.api_key.clone())
+            .text("talkgroup_num", record.to_id.clone())
+            .text("start_time", start.to_string())
+            .text("stop_time", (start + length.as_secs_f64().round() as i64).to_string())
+            .text("call_length", length.as_secs_f64().to_string())
+            .text("freq", "0")
+            .text("error_count", "0")
+            .text("spike_count", "0")
+            .text("emergency", "0")
+            .text("source_list", sources.to_string());
+
+        let endpoint = format!("{}/{}/upload", self.settings.url.trim_end_matches('/'), short_name);
+        receive(self.client.post(endpoint).multipart(form).send().await, files.mp3.len()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::tests::{mock_server, test_call, test_config, test_files, test_root};
+    use std::path::PathBuf;
+
+    fn sink(url: &str) -> OpenMhzSink {
+        let settings = OpenMhzSinkConfig {
+            url: format!("{}/", url),
+            api_key: "key".to_string(),
+            systems: [("Metro".to_string(), "metro".to_string())].into(),
+            ..OpenMhzSinkConfig::default()
+        };
+        OpenMhzSink::new("omhz".to_string(), settings, Client::new(), test_config())
+    }
+
+    /// The value of the text field `name` in a multipart body.
+    fn form_field<'a>(body: &'a str, name: &str) -> Option<&'a str> {
+        let (_, rest) = body.split_once(&format!("name=\"{}\"\r\n\r\n", name))?;
+        rest.split_once("\r\n--").map(|(value, _)| value)
+    }
+
+    #[tokio::test]
+    async fn posts_the_call_form_to_the_system() {
+        let (url, received) = mock_server(&[(200, "{\"success\": true}")]).await;
+        let (root, files) = (test_root(), test_files());
+        let receipt = sink(&url).upload(&test_call(&root, &files)).await.unwrap();
+        assert_eq!(receipt.bytes_sent, files.mp3.len() as u64);
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/metro/upload"));
+        let body = request.text();
+        for (name, value) in [
+            ("api_key", "key"),
+            ("talkgroup_num", "52197"),
+            ("start_time", "1704110400"),
+            ("stop_time", "1704110400"),
+            ("call_length", "0"),
+            ("freq", "0"),
+            ("emergency", "0"),
+            ("source_list", r#"[{"pos":0,"src":1504011}]"#),
+        ] {
+            assert_eq!(form_field(&body, name), Some(value), "{}", name);
+        }
+        assert!(body.contains("name=\"call\"; filename=\"20240101_120000Metro__TO_52197_FROM_1504011.mp3\""));
+        assert!(body.contains("not really an mp3"));
+        // OpenMHz has nowhere to put the transcript.
+        assert!(!body.contains("Engine 1 responding"));
+    }
+
+    #[tokio::test]
+    async fn a_call_without_a_source_has_an_empty_source_list() {
+        let (url, received) = mock_server(&[(200, "")]).await;
+        let root = test_root();
+        let mut files = test_files();
+        files.mp3_path = PathBuf::from("/rec/tg/20240101_120000Metro__TO_52197.mp3");
+        sink(&url).upload(&test_call(&root, &files)).await.unwrap();
+        assert_eq!(form_field(&received.lock().unwrap()[0].text(), "source_list"), Some("[]"));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_and_client_errors_are_not() {
+        let (url, _) = mock_server(&[(500, "oops"), (401, "bad key")]).await;
+        let (root, files) = (test_root(), test_files());
+        let sink = sink(&url);
+        assert!(sink.upload(&test_call(&root, &files)).await.unwrap_err().is_retryable());
+        assert!(!sink.upload(&test_call(&root, &files)).await.unwrap_err().is_retryable());
+    }
+}
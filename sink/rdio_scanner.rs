@@ -0,0 +1,69 @@
+use super::{receive, Call, UploadError, UploadReceipt, UploadSink};
+use crate::{
+    config::{Config, RdioScannerSinkConfig},
+    timestamp,
+};
+use async_trait::async_trait;
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Feeds an Rdio Scanner server through its `/api/call-upload` endpoint, the one its
+/// trunk-recorder and SDRTrunk integrations use. The transcript isn't sent; Rdio Scanner has
+/// nowhere to put it.
+pub struct RdioScannerSink {
+    name: String,
+    settings: RdioScannerSinkConfig,
+    endpoint: String,
+    client: Client,
+    config: Arc<Config>,
+}
+
+impl RdioScannerSink {
+    pub fn new(name: String, settings: RdioScannerSinkConfig, client: Client, config: Arc<Config>) -> Self {
+        let endpoint = format!("{}/api/call-upload", settings.url.trim_end_matches('/'));
+        RdioScannerSink { name, settings, endpoint, client, config }
+    }
+}
+
+#[async_trait]
+impl UploadSink for RdioScannerSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError> {
+        let (record, root, files) = (&call.record, call.root, call.files);
+        let system = self.settings.system_id(root).ok_or_else(|| {
+            UploadError::Misconfigured(format!("no Rdio Scanner system for {}", root.path.display()))
+        })?;
+        let date_time = timestamp::parse_timestamp(&record.timestamp, self.config.timestamp.timezone)
+            .ok_or_else(|| UploadError::InvalidTimestamp(record.timestamp.clone()))?;
+        debug!(call = ?record, system, "Uploading to Rdio Scanner");
+
+        let filename = files.mp3_path.file_name().unwrap().to_string_lossy().into_owned();
+        let audio = Part::bytes(files.mp3.clone()).file_name(filename.clone()).mime_str("audio/mpeg").expect("Invalid MIME type");
+        let mut form = Form::new()
+            .text("key", self.settings.api_key.clone())
+            .text("system", system.to_string())
+            .text("talkgroup", record.to_id.clone())
+            .text("dateTime", date_time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .text("audioName", filename)
+            .text("audioType", "audio/mpeg")
+            .part("audio", audio);
+        if let Some(source) = &record.from_id {
+            form = form.text("source", source.clone());
+        }
+        if let Some(label) = &root.system {
+            form = form.text("systemLabel", label.clone());
+        }
+        if let Some(label) = &record.to_alias {
+            form = form.text("talkgroupLabel", label.clone());
+        }
+
+        receive(self.client.post(&self.endpoint).multipart(form).send().await, files.mp3.len()).await
+    }
+}
@@ -0,0 +1,120 @@
+use super::{Call, UploadError, UploadReceipt, UploadSink};
+use crate::{
+    audio,
+    config::{Config, SqliteSinkConfig},
+    dead_letter::unix_now,
+    digest::sha256_hex,
+    timestamp,
+};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use rusqlite::{params, Connection};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Writes every call into a local SQLite database: the fields parsed from its filename, the
+/// transcript text, the mp3's path (and optionally the mp3 itself), its length and the
+/// hashes of both files. Works with no network at all, so a field laptop keeps a searchable
+/// record while the upload endpoint is unreachable.
+pub struct SqliteSink {
+    name: String,
+    store_audio: bool,
+    conn: Mutex<Connection>,
+    config: Arc<Config>,
+}
+
+impl SqliteSink {
+    pub fn open(name: String, settings: SqliteSinkConfig, config: Arc<Config>) -> rusqlite::Result<Self> {
+        let conn = Connection::open(&settings.path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS calls (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                stem            TEXT NOT NULL,
+                mp3_sha256      TEXT NOT NULL,
+                txt_sha256      TEXT NOT NULL,
+                mp3_path        TEXT NOT NULL,
+                txt_path        TEXT NOT NULL,
+                started_at      TEXT NOT NULL,
+                started_unix    INTEGER NOT NULL,
+                duration_secs   REAL,
+                system          TEXT,
+                site            TEXT,
+                channel         TEXT,
+                talkgroup       TEXT NOT NULL,
+                talkgroup_alias TEXT,
+                patch           INTEGER NOT NULL,
+                radio           TEXT,
+                radio_alias     TEXT,
+                protocol        TEXT,
+                encrypted       INTEGER NOT NULL,
+                transcript      TEXT NOT NULL,
+                audio           BLOB,
+                archived_at     INTEGER NOT NULL,
+                UNIQUE (stem, mp3_sha256, txt_sha256)
+            );
+            CREATE INDEX IF NOT EXISTS calls_started_unix ON calls (started_unix);
+            CREATE INDEX IF NOT EXISTS calls_talkgroup ON calls (talkgroup, started_unix);",
+        )?;
+        Ok(SqliteSink { name, store_audio: settings.store_audio, conn: Mutex::new(conn), config })
+    }
+}
+
+#[async_trait]
+impl UploadSink for SqliteSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn upload(&self, call: &Call<'_>) -> Result<UploadReceipt, UploadError> {
+        let (record, files) = (&call.record, call.files);
+        let started = timestamp::parse_timestamp(&record.timestamp, self.config.timestamp.timezone)
+            .ok_or_else(|| UploadError::InvalidTimestamp(record.timestamp.clone()))?;
+        let duration = audio::mp3_duration(&files.mp3).map(|d| d.as_secs_f64());
+        let stem = files.stem();
+        let (mp3_sha256, txt_sha256) = (sha256_hex(&files.mp3), sha256_hex(&files.txt));
+
+        let conn = self.conn.lock().unwrap();
+        // The same content archived again keeps its original row.
+        conn.execute(
+            "INSERT OR IGNORE INTO calls (
+                stem, mp3_sha256, txt_sha256, mp3_path, txt_path, started_at, started_unix, duration_secs,
+                system, site, channel, talkgroup, talkgroup_alias, patch, radio, radio_alias, protocol,
+                encrypted, transcript, audio, archived_at
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
+            params![
+                stem,
+                mp3_sha256,
+                txt_sha256,
+                files.mp3_path.to_string_lossy(),
+                files.txt_path.to_string_lossy(),
+                started.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                started.timestamp(),
+                duration,
+                call.system(),
+                record.site,
+                record.channel,
+                record.to_id,
+                record.to_alias,
+                record.patch,
+                record.from_id,
+                record.from_alias,
+                record.protocol,
+                record.encrypted,
+                String::from_utf8_lossy(&files.txt),
+                self.store_audio.then_some(&files.mp3),
+                unix_now() as i64,
+            ],
+        )
+        .map_err(|e| UploadError::Storage(e.to_string()))?;
+        let id: i64 = conn
+            .query_row(
+                "SELECT id FROM calls WHERE stem = ?1 AND mp3_sha256 = ?2 AND txt_sha256 = ?3",
+                params![stem, mp3_sha256, txt_sha256],
+                |row| row.get(0),
+            )
+            .map_err(|e| UploadError::Storage(e.to_string()))?;
+        debug!(id, "Archived call");
+        Ok(UploadReceipt { status: StatusCode::OK, call_id: Some(id.to_string()), body: String::new(), bytes_sent: 0 })
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, ops::Deref, str::FromStr};
+
+/// Placeholders a template may use, filled in from each call by `Call::fields`.
+pub const FIELDS: &[&str] = &[
+    "stem",
+    "system",
+    "site",
+    "channel",
+    "talkgroup",
+    "talkgroup_alias",
+    "radio",
+    "radio_alias",
+    "protocol",
+    "yyyy",
+    "mm",
+    "dd",
+    "hh",
+    "timestamp",
+    "unix",
+    "duration",
+];
+
+/// Placeholders an `EventTemplate` may use on top of `FIELDS`, describing how a sink fared with
+/// the call. Filled in by `CallEvent`.
+pub const EVENT_FIELDS: &[&str] = &["sink", "result", "status", "call_id", "error", "transcript", "attempts"];
+
+/// Text with `{field}` placeholders naming entries of `FIELDS`, checked when the config is
+/// loaded. `{{` and `}}` stand for literal braces.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+impl Template {
+    /// Parses `s`, allowing only placeholders named in `fields`.
+    fn parse(s: &str, fields: &[&str]) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("unclosed '{', write '{{' for a literal brace".to_string()),
+                        }
+                    }
+                    if !fields.contains(&name.as_str()) {
+                        return Err(format!("unknown field {{{}}}, expected one of {}", name, fields.join(", ")));
+                    }
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Field(name));
+                }
+                '}' => return Err("unmatched '}', write '}}' for a literal brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        Ok(Template { source: s.to_string(), parts })
+    }
+
+    /// Fills in the placeholders, passing each value through `escape` first. Fields the call
+    /// doesn't have come out empty.
+    pub fn render_with(&self, fields: &HashMap<&str, String>, escape: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Field(name) => out.push_str(&escape(fields.get(name.as_str()).map_or("", String::as_str))),
+            }
+        }
+        out
+    }
+
+    pub fn render(&self, fields: &HashMap<&str, String>) -> String {
+        self.render_with(fields, str::to_string)
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::parse(s, FIELDS)
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A `Template` that may also use `EVENT_FIELDS`, for messages about a call's outcome.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct EventTemplate(Template);
+
+impl Deref for EventTemplate {
+    type Target = Template;
+
+    fn deref(&self) -> &Template {
+        &self.0
+    }
+}
+
+impl FromStr for EventTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::parse(s, &[FIELDS, EVENT_FIELDS].concat()).map(EventTemplate)
+    }
+}
+
+impl TryFrom<String> for EventTemplate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pairs: &[(&'static str, &str)]) -> HashMap<&'static str, String> {
+        pairs.iter().map(|(name, value)| (*name, value.to_string())).collect()
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let template: Template = "{system}/{talkgroup}/{yyyy}/{stem}.mp3".parse().unwrap();
+        let values = fields(&[("system", "Metro"), ("talkgroup", "52197"), ("yyyy", "2024"), ("stem", "a")]);
+        assert_eq!(template.render(&values), "Metro/52197/2024/a.mp3");
+        assert_eq!(template.to_string(), "{system}/{talkgroup}/{yyyy}/{stem}.mp3");
+    }
+
+    #[test]
+    fn missing_fields_render_empty() {
+        let template: Template = "{system}/{talkgroup}".parse().unwrap();
+        assert_eq!(template.render(&fields(&[("talkgroup", "52197")])), "/52197");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let template: EventTemplate = r#"{{"text": "{transcript}", "tg": {{"id": "{talkgroup}"}}}}"#.parse().unwrap();
+        let rendered = template.render(&fields(&[("transcript", "hello"), ("talkgroup", "52197")]));
+        assert_eq!(rendered, r#"{"text": "hello", "tg": {"id": "52197"}}"#);
+        assert_eq!("{{}}".parse::<Template>().unwrap().render(&HashMap::new()), "{}");
+    }
+
+    #[test]
+    fn escape_applies_to_values_only() {
+        let template: Template = "{{{talkgroup}}}".parse().unwrap();
+        let rendered = template.render_with(&fields(&[("talkgroup", "52197")]), |value| format!("<{}>", value));
+        assert_eq!(rendered, "{<52197>}");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let error = "{talkgroup}/{tg}".parse::<Template>().unwrap_err();
+        assert!(error.starts_with("unknown field {tg}, expected one of stem, system"), "{}", error);
+        assert!("{}".parse::<Template>().unwrap_err().starts_with("unknown field {}"));
+    }
+
+    #[test]
+    fn event_fields_only_in_event_templates() {
+        assert!("{sink}: {transcript}".parse::<EventTemplate>().is_ok());
+        let error = "{sink}".parse::<Template>().unwrap_err();
+        assert!(error.starts_with("unknown field {sink}"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unbalanced_braces() {
+        assert_eq!("{talkgroup".parse::<Template>().unwrap_err(), "unclosed '{', write '{{' for a literal brace");
+        assert_eq!("talkgroup}".parse::<Template>().unwrap_err(), "unmatched '}', write '}}' for a literal brace");
+        // A bare JSON object reads as a placeholder named after its contents.
+        assert!(r#"{"text": "{transcript}"}"#.parse::<EventTemplate>().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Timezone of the SDRTrunk host, which writes filename timestamps in its local time.
+/// Configured as `"local"` (this machine's zone), `"UTC"`, or an IANA name like `"America/Chicago"`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SourceTimezone {
+    Local,
+    Named(Tz),
+}
+
+impl FromStr for SourceTimezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("local") {
+            Ok(SourceTimezone::Local)
+        } else {
+            s.parse::<Tz>().map(SourceTimezone::Named).map_err(|_| format!("unknown timezone {:?}", s))
+        }
+    }
+}
+
+impl TryFrom<String> for SourceTimezone {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// How the call timestamp is sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// RFC 3339 in UTC, e.g. `2024-01-01T17:00:00Z`.
+    Rfc3339,
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// Milliseconds since the Unix epoch.
+    UnixMillis,
+    /// The `YYYYMMDD_HHMMSS` string from the filename, unchanged.
+    Original,
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "unix" => Ok(TimestampFormat::Unix),
+            "unix_millis" => Ok(TimestampFormat::UnixMillis),
+            "original" => Ok(TimestampFormat::Original),
+            _ => Err(format!("expected rfc3339, unix, unix_millis or original, got {:?}", s)),
+        }
+    }
+}
+
+/// Interprets an SDRTrunk `YYYYMMDD_HHMMSS` timestamp in `zone`.
+///
+/// Around DST changes a wall-clock time can occur twice (fall back), in which case the
+/// earlier instant is used, or not at all (spring forward), in which case the time is
+/// read as if the clock had not yet moved, i.e. one hour later in the new offset.
+pub fn parse_timestamp(raw: &str, zone: SourceTimezone) -> Option<DateTime<Utc>> {
+    let naive = parse_wall_clock(raw)?;
+    match zone {
+        SourceTimezone::Local => resolve(&Local, naive),
+        SourceTimezone::Named(tz) => resolve(&tz, naive),
+    }
+}
+
+/// Reads an SDRTrunk `YYYYMMDD_HHMMSS` timestamp as the recorder's wall-clock time.
+pub fn parse_wall_clock(raw: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(raw, "%Y%m%d_%H%M%S").ok()
+}
+
+fn resolve<Z: TimeZone>(zone: &Z, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match zone.from_local_datetime(&naive) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Some(time.with_timezone(&Utc)),
+        LocalResult::None => zone
+            .from_local_datetime(&(naive + Duration::hours(1)))
+            .earliest()
+            .map(|time| time.with_timezone(&Utc)),
+    }
+}
+
+/// Renders a filename timestamp for upload, or `None` if it isn't a valid date and time.
+pub fn format_timestamp(raw: &str, zone: SourceTimezone, format: TimestampFormat) -> Option<String> {
+    let time = parse_timestamp(raw, zone)?;
+    Some(match format {
+        TimestampFormat::Rfc3339 => time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        TimestampFormat::Unix => time.timestamp().to_string(),
+        TimestampFormat::UnixMillis => time.timestamp_millis().to_string(),
+        TimestampFormat::Original => raw.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_york() -> SourceTimezone {
+        "America/New_York".parse().unwrap()
+    }
+
+    fn utc(rfc3339: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc))
+    }
+
+    #[test]
+    fn standard_and_daylight_time() {
+        assert_eq!(parse_timestamp("20240101_120000", new_york()), utc("2024-01-01T17:00:00Z"));
+        assert_eq!(parse_timestamp("20240701_120000", new_york()), utc("2024-07-01T16:00:00Z"));
+    }
+
+    #[test]
+    fn spring_forward_gap_reads_as_if_the_clock_had_not_moved() {
+        // 02:30 doesn't exist on 2024-03-10; it's taken as 03:30 EDT.
+        assert_eq!(parse_timestamp("20240310_023000", new_york()), utc("2024-03-10T07:30:00Z"));
+    }
+
+    #[test]
+    fn fall_back_overlap_takes_the_earlier_instant() {
+        // 01:30 happens twice on 2024-11-03; the first is still EDT.
+        assert_eq!(parse_timestamp("20241103_013000", new_york()), utc("2024-11-03T05:30:00Z"));
+    }
+
+    #[test]
+    fn each_format() {
+        let format = |format| format_timestamp("20240101_120000", new_york(), format);
+        assert_eq!(format(TimestampFormat::Rfc3339).as_deref(), Some("2024-01-01T17:00:00Z"));
+        assert_eq!(format(TimestampFormat::Unix).as_deref(), Some("1704128400"));
+        assert_eq!(format(TimestampFormat::UnixMillis).as_deref(), Some("1704128400000"));
+        assert_eq!(format(TimestampFormat::Original).as_deref(), Some("20240101_120000"));
+        let in_utc = format_timestamp("20240101_120000", "UTC".parse().unwrap(), TimestampFormat::Rfc3339);
+        assert_eq!(in_utc.as_deref(), Some("2024-01-01T12:00:00Z"));
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        for raw in ["20240230_120000", "20240101_250000", "2024-01-01T12:00:00", ""] {
+            assert_eq!(format_timestamp(raw, new_york(), TimestampFormat::Original), None, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn timezone_names() {
+        assert_eq!("local".parse(), Ok(SourceTimezone::Local));
+        assert_eq!("LOCAL".parse(), Ok(SourceTimezone::Local));
+        assert!("Mars/Olympus_Mons".parse::<SourceTimezone>().is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::TlsConfig;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    client::WebPkiServerVerifier,
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+use std::{fmt, path::PathBuf, sync::Arc};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+#[derive(Debug)]
+pub enum TlsError {
+    Pem(PathBuf, rustls::pki_types::pem::Error),
+    NoCertificates(PathBuf),
+    InvalidPin(String),
+    Rustls(rustls::Error),
+    Verifier(rustls::client::VerifierBuilderError),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsError::Pem(path, e) => write!(f, "failed to load {:?}: {}", path, e),
+            TlsError::NoCertificates(path) => write!(f, "no certificates found in {:?}", path),
+            TlsError::InvalidPin(pin) => write!(f, "{:?} is not a base64 SHA-256 SPKI hash", pin),
+            TlsError::Rustls(e) => write!(f, "{}", e),
+            TlsError::Verifier(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Builds the rustls configuration used for every upload: the bundled web roots plus any
+/// configured CA bundle, optional SPKI pins, and an optional client certificate for mTLS.
+pub fn client_config(tls: &TlsConfig) -> Result<ClientConfig, TlsError> {
+    let provider = Arc::new(ring::default_provider());
+
+    let mut roots = RootCertStore::empty();
+    if tls.system_roots {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    if let Some(ca_bundle) = &tls.ca_bundle {
+        let certs = load_certs(ca_bundle)?;
+        roots.add_parsable_certificates(certs);
+    }
+
+    let pins = tls.pinned_spki_sha256.iter().map(|pin| parse_pin(pin)).collect::<Result<Vec<_>, _>>()?;
+    let verifier: Arc<dyn ServerCertVerifier> = if tls.insecure_skip_verify {
+        Arc::new(InsecureVerifier { provider: provider.clone() })
+    } else {
+        let webpki = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+            .build()
+            .map_err(TlsError::Verifier)?;
+        if pins.is_empty() {
+            webpki
+        } else {
+            Arc::new(PinnedVerifier { inner: webpki, pins })
+        }
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(TlsError::Rustls)?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert), Some(key)) => {
+            let chain = load_certs(cert)?;
+            let key = PrivateKeyDer::from_pem_file(key).map_err(|e| TlsError::Pem(key.clone(), e))?;
+            builder.with_client_auth_cert(chain, key).map_err(TlsError::Rustls)
+        }
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+fn load_certs(path: &PathBuf) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| TlsError::Pem(path.clone(), e))?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.clone()));
+    }
+    Ok(certs)
+}
+
+/// Accepts pins as plain base64 or in the `sha256/<base64>` form used by HPKP and curl.
+fn parse_pin(pin: &str) -> Result<[u8; 32], TlsError> {
+    let encoded = pin.trim().trim_start_matches("sha256/");
+    BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| TlsError::InvalidPin(pin.to_string()))
+}
+
+fn spki_sha256(cert: &CertificateDer) -> Option<[u8; 32]> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+    Some(Sha256::digest(cert.tbs_certificate.subject_pki.raw).into())
+}
+
+/// Normal WebPKI verification, after which one of the certificates in the chain must carry
+/// a pinned public key. Pinning an intermediate or root key keeps working across renewals.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(spki_sha256)
+            .any(|hash| self.pins.contains(&hash));
+        if pinned {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            let presented = spki_sha256(end_entity).map(|hash| BASE64.encode(hash)).unwrap_or_default();
+            Err(rustls::Error::General(format!("server key sha256/{} does not match any pin", presented)))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Accepts any server certificate. Handshake signatures are still checked so the
+/// connection is at least encrypted to whoever holds the presented key.
+#[derive(Debug)]
+struct InsecureVerifier {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
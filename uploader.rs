@@ -1,90 +1,697 @@
+mod audio;
+mod backfill;
+mod coalesce;
+mod config;
+mod dead_letter;
+mod digest;
+mod event;
+mod filename;
+mod health;
+mod ledger;
+mod logging;
+mod metrics;
+mod mqtt;
+mod queue;
+mod readiness;
+mod retry;
+mod server;
+mod sink;
+mod template;
+mod timestamp;
+mod tls;
+mod webhook;
+
+use clap::{Parser, Subcommand};
+use coalesce::Coalescer;
+use config::{Config, Overrides, RootConfig, TlsConfig};
+use dead_letter::DeadLetterReason;
 use dotenv::dotenv;
-use notify::{recommended_watcher, RecursiveMode, Result as NotifyResult, Watcher};
-use regex::Regex;
-use reqwest::{Client, multipart::{Form, Part}};
-use std::{env, path::PathBuf, sync::mpsc::channel};
-use tokio::runtime::Runtime;
-use std::fs;
-
-fn main() -> NotifyResult<()> {
+use event::Notifier;
+use health::Health;
+use ledger::{Ledger, LedgerEntry};
+use logging::LogHandle;
+use metrics::Metrics;
+use notify::{
+    event::{AccessKind, AccessMode},
+    recommended_watcher, Event, EventKind, RecursiveMode, Result as NotifyResult, Watcher,
+};
+use queue::{Job, UploadQueue};
+use readiness::{Readiness, ReadinessTracker};
+use reqwest::Client;
+use retry::RetryPolicy;
+use sink::{Call, CallFiles, UploadError, UploadSink};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{self, ExitCode},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    runtime::Runtime,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
+
+/// How often pairs waiting to finish being written are re-checked.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a closed file that hasn't been paired is remembered as closed, e.g. an mp3
+/// waiting on a slow transcription. After that it has to settle like any other file.
+const FORGET_UNPAIRED_AFTER: Duration = Duration::from_secs(600);
+/// How often idle workers re-check the queue for jobs whose retry delay has passed.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Exit status when the shutdown deadline cut uploads off; they stay queued for the next
+/// run. Matches `EX_TEMPFAIL` from sysexits.h.
+const EXIT_UPLOADS_INTERRUPTED: u8 = 75;
+
+#[derive(Debug, Parser)]
+#[command(about = "Uploads SDRTrunk call recordings and their transcripts")]
+struct Cli {
+    #[command(flatten)]
+    overrides: Overrides,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Watch the configured directories and upload calls (the default)
+    Run,
+    /// Check the configuration and report every problem found
+    ValidateConfig,
+    /// Queue every complete pair in the watched directories that hasn't been uploaded
+    Backfill,
+    /// Inspect or edit the record of already uploaded calls
+    #[command(subcommand)]
+    Ledger(LedgerCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum LedgerCommand {
+    /// List uploaded calls, newest first
+    List {
+        /// Only calls whose filename stem contains this text
+        #[arg(long)]
+        stem: Option<String>,
+        /// Only calls delivered to this sink
+        #[arg(long)]
+        sink: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Forget uploaded calls so they will be uploaded again if seen
+    Purge {
+        /// Only calls whose filename stem contains this text
+        #[arg(long)]
+        stem: Option<String>,
+        /// Only calls delivered to this sink, so they are sent to it again
+        #[arg(long)]
+        sink: Option<String>,
+        /// Only calls uploaded more than this many days ago
+        #[arg(long)]
+        older_than_days: Option<f64>,
+        /// Required to purge without any filter
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+fn main() -> ExitCode {
     dotenv().ok();
-    let monitored_directory = env::var("MONITORED_DIRECTORY")
-        .expect("MONITORED_DIRECTORY environment variable not set");
-    let root_path_buf = PathBuf::from(&monitored_directory);
-    println!("Monitoring directory: {:?}", root_path_buf);
+    let cli = Cli::parse();
+    let config = Config::load(&cli.overrides).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        process::exit(1);
+    });
+    let log = logging::init(&config.log.level, config.log.format).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: log.level: {}", e);
+        process::exit(1);
+    });
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => {
+            exit_if_invalid(&config);
+            run(config, cli.overrides, log).unwrap_or_else(|e| {
+                error!("Watcher error: {}", e);
+                ExitCode::FAILURE
+            })
+        }
+        Command::ValidateConfig => {
+            exit_if_invalid(&config);
+            println!("Configuration OK");
+            ExitCode::SUCCESS
+        }
+        Command::Backfill => {
+            exit_if_invalid(&config);
+            let queue = UploadQueue::open(&config.queue.path).expect("Failed to open upload queue");
+            let ledger = Ledger::open(&config.ledger.path).expect("Failed to open upload ledger");
+            let metrics = Metrics::new();
+            let candidates = backfill::scan(
+                &config.watch.root_paths(),
+                &ledger,
+                &config.sink_names(),
+                config.backfill.max_age(),
+                config.backfill.order,
+            );
+            info!(calls = candidates.len(), "Backfill found calls to upload");
+            // Pairs modified within the settle window may still be being written.
+            let (settled, unsettled): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .partition(|candidate| candidate.modified.elapsed().unwrap_or_default() >= config.watch.settle());
+            for candidate in settled {
+                enqueue_pair(&config, &queue, &metrics, &candidate.mp3_path, &candidate.txt_path);
+            }
+            if !unsettled.is_empty() {
+                warn!(calls = unsettled.len(), "Skipped calls still being written; run backfill again once they settle");
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Ledger(command) => {
+            if let Err(e) = run_ledger_command(&config, command) {
+                eprintln!("Ledger error: {}", e);
+                process::exit(1);
+            }
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+fn exit_if_invalid(config: &Config) {
+    let problems = config.validate();
+    for problem in &problems {
+        eprintln!("Invalid configuration: {}", problem);
+    }
+    if !problems.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn run_ledger_command(config: &Config, command: LedgerCommand) -> rusqlite::Result<()> {
+    let ledger = Ledger::open(&config.ledger.path)?;
+    match command {
+        LedgerCommand::List { stem, sink, limit } => {
+            for entry in ledger.list(stem.as_deref(), sink.as_deref(), limit)? {
+                let uploaded_at = chrono::DateTime::from_timestamp(entry.uploaded_at, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default();
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    uploaded_at,
+                    entry.sink,
+                    entry.status,
+                    entry.call_id.as_deref().unwrap_or("-"),
+                    entry.stem,
+                    entry.mp3_path
+                );
+            }
+        }
+        LedgerCommand::Purge { stem, sink, older_than_days, all } => {
+            if stem.is_none() && sink.is_none() && older_than_days.is_none() && !all {
+                eprintln!("Refusing to purge the whole ledger without --all");
+                process::exit(1);
+            }
+            let before = older_than_days.map(|days| dead_letter::unix_now() as i64 - (days * 86_400.0) as i64);
+            let purged = ledger.purge(stem.as_deref(), sink.as_deref(), before)?;
+            println!("Purged {} ledger entr{}", purged, if purged == 1 { "y" } else { "ies" });
+        }
+    }
+    Ok(())
+}
+
+/// Watches and uploads until SIGINT or SIGTERM. Returns success if every in-flight upload
+/// finished before `shutdown.deadline_secs`, or `EXIT_UPLOADS_INTERRUPTED` if some had to be
+/// abandoned and were put back in the queue.
+fn run(config: Config, overrides: Overrides, log: LogHandle) -> NotifyResult<ExitCode> {
+    let config = Arc::new(config);
+    for root in &config.watch.roots {
+        info!(directory = %root.path.display(), system = root.system.as_deref(), "Monitoring directory");
+    }
+    let queue = Arc::new(UploadQueue::open(&config.queue.path).expect("Failed to open upload queue"));
+    // Anything still marked in flight was interrupted by a crash or restart. Only `run` does
+    // this: `backfill` may run alongside a daemon whose uploads really are in flight.
+    match queue.requeue_in_flight() {
+        Ok(0) => {}
+        Ok(recovered) => info!(recovered, "Recovered interrupted uploads from queue"),
+        Err(e) => error!("Failed to recover interrupted uploads from queue: {}", e),
+    }
+    info!(queue = %config.queue.path.display(), pending = queue.len().unwrap_or(0), "Opened upload queue");
+    let ledger = Arc::new(Ledger::open(&config.ledger.path).expect("Failed to open upload ledger"));
 
     let rt = Runtime::new().unwrap();
-    // Changing the block to handle Result
     rt.block_on(async {
-        let (tx, rx) = channel();
-
-        // Handle result of recommended_watcher
-        let mut watcher = recommended_watcher(move |res| tx.send(res).unwrap()).unwrap();
-        // Handle result of watcher.watch
-        watcher.watch(&root_path_buf, RecursiveMode::Recursive).unwrap();
-
-        let client = Client::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .expect("Failed to create HTTP client");
-        while let Ok(event) = rx.recv() {
-            match event {
-                Ok(event) => {
-                    println!("Processing event: {:?}", event);
+        // The notify callback runs on the watcher's own thread; an unbounded channel means a
+        // burst of events never stalls it, while uploads are bounded by the worker count.
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut watcher = recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        })?;
+        for root in &config.watch.roots {
+            watcher.watch(&root.path, RecursiveMode::Recursive)?;
+        }
+        let health = Arc::new(Health::new());
+        health.set_watcher_registered();
+
+        if config.tls.insecure_skip_verify {
+            warn!("TLS certificate verification is DISABLED (tls.insecure_skip_verify)");
+            warn!("Uploads and the API key can be intercepted by anyone on the network path");
+        }
+        // `[tls]` describes the `[upload]` server, so only multipart sinks use all of it;
+        // other services and webhooks get the public roots and `ca_bundle`.
+        let http_client = |tls: &TlsConfig| {
+            Client::builder()
+                .use_preconfigured_tls(tls::client_config(tls).expect("Failed to build TLS configuration"))
+                .timeout(config.upload.timeout())
+                .connect_timeout(config.upload.connect_timeout())
+                .build()
+                .expect("Failed to create HTTP client")
+        };
+        let clients = sink::Clients { upload: http_client(&config.tls), public: http_client(&config.tls.shared()) };
+        let sinks = Arc::new(sink::build(&config, &clients).expect("Failed to open sink"));
+        let notifier = Arc::new(Notifier::start(config.clone(), &clients.public));
+
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        tokio::spawn(async move {
+            let signal = wait_for_signal().await;
+            info!(signal, "Shutting down; send the signal again to exit immediately");
+            let _ = shutdown_tx.send(true);
+            let signal = wait_for_signal().await;
+            warn!(signal, "Signalled again, exiting without waiting for uploads");
+            process::exit(EXIT_UPLOADS_INTERRUPTED.into());
+        });
+        #[cfg(unix)]
+        tokio::spawn(reload_log_filter_on_hangup(overrides, log));
+        #[cfg(not(unix))]
+        let _ = (overrides, log);
+
+        let metrics = Arc::new(Metrics::new());
+        if let Some(address) = config.http.listen {
+            let server =
+                server::serve(address, config.clone(), metrics.clone(), health.clone(), queue.clone(), shutdown.clone());
+            tokio::spawn(async move {
+                if let Err(e) = server.await {
+                    error!("HTTP server failed: {}", e);
+                }
+            });
+        }
+
+        let mut workers: Vec<JoinHandle<()>> = (0..config.upload.workers)
+            .map(|_| {
+                tokio::spawn(run_upload_worker(
+                    sinks.clone(),
+                    notifier.clone(),
+                    queue.clone(),
+                    ledger.clone(),
+                    metrics.clone(),
+                    health.clone(),
+                    config.clone(),
+                    shutdown.clone(),
+                ))
+            })
+            .collect();
+
+        run_intake(rx, config.clone(), queue.clone(), ledger, metrics, health, shutdown).await;
+        drop(watcher);
+
+        // Workers stop claiming jobs once shutdown is signalled, so this only waits for
+        // uploads that were already under way.
+        let deadline = config.shutdown.deadline();
+        info!(deadline = ?deadline, "Waiting for in-flight uploads to finish");
+        let drained = tokio::time::timeout(deadline, async {
+            for worker in &mut workers {
+                let _ = worker.await;
+            }
+        })
+        .await
+        .is_ok();
+        notifier.close().await;
+        if drained {
+            info!(queued = queue.len().unwrap_or(0), "Shutdown complete");
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        for worker in &workers {
+            worker.abort();
+        }
+        match queue.requeue_in_flight() {
+            Ok(n) => warn!(interrupted = n, "Shutdown deadline passed; interrupted uploads left in queue for the next run"),
+            Err(e) => error!("Shutdown deadline passed and interrupted uploads could not be requeued: {}", e),
+        }
+        Ok(ExitCode::from(EXIT_UPLOADS_INTERRUPTED))
+    })
+}
+
+/// Resolves on the first SIGINT or SIGTERM, returning the signal's name.
+async fn wait_for_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        let mut interrupt = signal(SignalKind::interrupt()).expect("Failed to install SIGINT handler");
+        tokio::select! {
+            _ = interrupt.recv() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+/// Re-reads `log.level` from the config file and environment on SIGHUP, so the filter can
+/// be narrowed or widened without a restart.
+#[cfg(unix)]
+async fn reload_log_filter_on_hangup(overrides: Overrides, log: LogHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        let result = Config::load(&overrides)
+            .map_err(|e| e.to_string())
+            .and_then(|config| log.set_filter(&config.log.level).map(|()| config.log.level));
+        match result {
+            Ok(level) => info!(level, "Reloaded log filter"),
+            Err(e) => error!("Failed to reload log filter, keeping the current one: {}", e),
+        }
+    }
+}
+
+/// Turns raw watcher events into queued pairs: events are first collapsed per call, then
+/// each complete pair waits, keyed by mp3 path, until both files have finished being written.
+async fn run_intake(
+    mut events: mpsc::UnboundedReceiver<NotifyResult<Event>>,
+    config: Arc<Config>,
+    queue: Arc<UploadQueue>,
+    ledger: Arc<Ledger>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut coalescer = Coalescer::new(config.watch.debounce(), config.watch.dedupe_window());
+    let mut waiting: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut readiness = ReadinessTracker::new(config.watch.settle());
+
+    // The watcher is already running, so anything written during the scan is seen by both;
+    // the queue and coalescer keep that from becoming a second upload.
+    if config.backfill.on_startup {
+        let (scan_config, scan_ledger) = (config.clone(), ledger.clone());
+        let candidates = tokio::task::spawn_blocking(move || {
+            let backfill = &scan_config.backfill;
+            backfill::scan(
+                &scan_config.watch.root_paths(),
+                &scan_ledger,
+                &scan_config.sink_names(),
+                backfill.max_age(),
+                backfill.order,
+            )
+        })
+        .await
+        .unwrap_or_default();
+        info!(calls = candidates.len(), "Backfill found calls to upload");
+        for candidate in candidates {
+            // Pairs modified within the settle window may still be being written.
+            if candidate.modified.elapsed().unwrap_or_default() < config.watch.settle() {
+                waiting.insert(candidate.mp3_path, candidate.txt_path);
+            } else {
+                enqueue_pair(&config, &queue, &metrics, &candidate.mp3_path, &candidate.txt_path);
+                coalescer.finish(&candidate.mp3_path);
+            }
+        }
+    }
+
+    let root_paths = config.watch.root_paths();
+    let mut tick = tokio::time::interval(READINESS_POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(Ok(event)) => {
+                    trace!(?event, "Watcher event");
+                    metrics.events.inc();
+                    let closed_for_write = config.watch.close_write
+                        && event.kind == EventKind::Access(AccessKind::Close(AccessMode::Write));
                     for path in event.paths {
-                        println!("Detected change in path: {:?}", path);
-                        if should_process_file(&path, &root_path_buf) {
-                            if let Some((mp3_path, txt_path)) = extract_file_info(&path) {
-                                upload_file(&client, &mp3_path, &txt_path).await;
+                        if should_process_file(&path, &root_paths) {
+                            let call_file = path.extension().is_some_and(|ext| ext == "mp3" || ext == "txt");
+                            if !coalescer.observe(&path) {
+                                trace!(path = %path.display(), "Ignoring event for already queued call");
+                            } else if closed_for_write && call_file {
+                                readiness.closed_for_write(&path);
                             }
+                        } else {
+                            metrics.files_filtered.inc();
                         }
                     }
-                },
-                Err(e) => eprintln!("Error handling event: {:?}", e),
+                }
+                Some(Err(e)) => warn!("Watcher error: {}", e),
+                None => break,
+            },
+            _ = tick.tick() => {}
+            _ = shutdown.changed() => break,
+        }
+        health.beat();
+
+        for stem in coalescer.due() {
+            let mut mp3_path = stem.into_os_string();
+            mp3_path.push(".mp3");
+            if let Some((mp3_path, txt_path)) = extract_file_info(&PathBuf::from(mp3_path)) {
+                waiting.insert(mp3_path, txt_path);
             }
         }
-    });
+        waiting.retain(|mp3_path, txt_path| {
+            match (readiness.check(mp3_path), readiness.check(txt_path)) {
+                (Readiness::Ready, Readiness::Ready) => {
+                    enqueue_pair(&config, &queue, &metrics, mp3_path, txt_path);
+                    coalescer.finish(mp3_path);
+                }
+                (Readiness::Missing, _) | (_, Readiness::Missing) => {
+                    warn!(mp3 = %mp3_path.display(), "Pair disappeared before upload")
+                }
+                _ => return true,
+            }
+            readiness.forget(mp3_path);
+            readiness.forget(txt_path);
+            false
+        });
+        readiness.prune(FORGET_UNPAIRED_AFTER);
+    }
 
-    Ok(())
+    // Half-written pairs are left on disk rather than queued, so a restart never uploads a
+    // truncated file; a backfill finds them once they are complete.
+    let unqueued = waiting.len() + coalescer.pending();
+    if unqueued > 0 && config.backfill.on_startup {
+        info!(calls = unqueued, "Calls still being written were not queued; the startup backfill will pick them up");
+    } else if unqueued > 0 {
+        warn!(
+            calls = unqueued,
+            "Calls still being written were not queued and backfill.on_startup is off; run `uploader backfill` to queue them"
+        );
+    }
 }
 
-fn should_process_file(file_path: &PathBuf, root_path: &PathBuf) -> bool {
-    let should_process = file_path.parent() != Some(root_path) && file_path.is_file();
-    println!("Should process {:?}: {}", file_path, should_process);
-    should_process
-}
-
-async fn upload_file(client: &Client, mp3_path: &PathBuf, txt_path: &PathBuf) {
-    println!("Uploading files: {:?}, {:?}", mp3_path, txt_path);
-    let filename = mp3_path.file_name().unwrap().to_str().unwrap();
-    if let Some((timestamp, talkgroup_id, radio_id)) = parse_filename(filename) {
-        let mp3_bytes = fs::read(mp3_path).expect("Failed to read mp3 file");
-        let txt_bytes = fs::read(txt_path).expect("Failed to read txt file");
-        println!("timestamp: {:?} \n talkgroup_id: {:?} \n radio_id: {:?}", timestamp, talkgroup_id, radio_id);
-        let mp3_part = Part::bytes(mp3_bytes).file_name(filename.to_string()).mime_str("audio/mpeg").expect("Invalid MIME type");
-        let txt_filename = txt_path.file_name().unwrap().to_str().unwrap();
-        let txt_part = Part::bytes(txt_bytes).file_name(txt_filename.to_string()).mime_str("text/plain").expect("Invalid MIME type");
-
-        let form = Form::new()
-            .text("talkgroupId", talkgroup_id)
-            .text("timestamp", timestamp)
-            .text("radioId", radio_id)
-            .part("mp3", mp3_part)
-            .part("transcription", txt_part);
-
-        match client.post("https://some.host:3000/api/upload")
-            .header("X-API-Key", "12345678")
-            .multipart(form)
-            .send()
-            .await {
-                Ok(response) => println!("Upload successful: {:?}", response),
-                Err(e) => eprintln!("Upload failed: {}", e),
+fn enqueue_pair(config: &Config, queue: &UploadQueue, metrics: &Metrics, mp3_path: &Path, txt_path: &Path) {
+    match queue.enqueue(mp3_path, txt_path, &config.sink_names()) {
+        Ok(true) => {
+            info!(mp3 = %mp3_path.display(), "Queued for upload");
+            metrics.pairs_found.inc();
+            if let Some(call) = config.parse_call(mp3_path).filter(|call| call.from_id.is_none()) {
+                metrics.calls_without_source.inc();
+                debug!(talkgroup = call.to_id, timestamp = call.timestamp, "No source radio for call");
             }
+        }
+        Ok(false) => debug!(mp3 = %mp3_path.display(), "Already queued"),
+        Err(e) => error!(mp3 = %mp3_path.display(), "Failed to queue: {}", e),
     }
 }
 
-fn extract_file_info(file_path: &PathBuf) -> Option<(PathBuf, PathBuf)> {
-    println!("Extracting file info for: {:?}", file_path);
+fn should_process_file(file_path: &Path, root_paths: &[PathBuf]) -> bool {
+    let in_root = root_paths.iter().any(|root| file_path.parent() == Some(root.as_path()));
+    !in_root && file_path.is_file()
+}
+
+/// Pulls jobs off the queue and hands each to its sink. Retryable failures go back on the
+/// queue according to the sink's retry policy; anything else is moved to the dead-letter
+/// directory. Stops claiming new jobs once `shutdown` is signalled, after finishing the
+/// current one.
+#[allow(clippy::too_many_arguments)]
+async fn run_upload_worker(
+    sinks: Arc<Vec<Box<dyn UploadSink>>>,
+    notifier: Arc<Notifier>,
+    queue: Arc<UploadQueue>,
+    ledger: Arc<Ledger>,
+    metrics: Arc<Metrics>,
+    health: Arc<Health>,
+    config: Arc<Config>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let retry_policies: HashMap<&str, RetryPolicy> =
+        config.sinks.iter().map(|sink| (sink.name.as_str(), sink.retry_policy(&config.retry))).collect();
+    while !*shutdown.borrow() {
+        let job = match queue.claim() {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                tokio::select! {
+                    _ = queue.wait(QUEUE_POLL_INTERVAL) => {}
+                    _ = shutdown.changed() => {}
+                }
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to read upload queue: {}", e);
+                tokio::select! {
+                    _ = tokio::time::sleep(QUEUE_POLL_INTERVAL) => {}
+                    _ = shutdown.changed() => {}
+                }
+                continue;
+            }
+        };
+        let attempt = job.attempts + 1;
+        let stem = job.mp3_path.file_stem().unwrap_or_default().to_string_lossy();
+        // Numeric so a filter like `uploader[call{talkgroup=1234}]=debug` matches it.
+        let talkgroup = config.parse_call(&job.mp3_path).and_then(|call| call.to_id.parse::<u64>().ok());
+        let span = info_span!("call", %stem, talkgroup, sink = %job.sink, attempt);
+        let (Some(sink), Some(retry_policy)) =
+            (sinks.iter().find(|sink| sink.name() == job.sink), retry_policies.get(job.sink.as_str()))
+        else {
+            span.in_scope(|| {
+                // Sinks that already have the call skip it by the ledger.
+                warn!("Sink is no longer configured; queueing the call for the current sinks instead");
+                let result = queue
+                    .enqueue(&job.mp3_path, &job.txt_path, &config.sink_names())
+                    .and_then(|_| queue.ack(job.id));
+                if let Err(e) = result {
+                    error!("Failed to update upload queue: {}", e);
+                }
+            });
+            continue;
+        };
+        let outcome = process_job(sink.as_ref(), &ledger, &metrics, &health, &config, &job).instrument(span.clone()).await;
+        let delay = match &outcome {
+            Err(e) if e.is_retryable() => retry_policy.delay_for(attempt, e.retry_after()),
+            _ => None,
+        };
+        // Only the final outcome of an actual attempt is reported, before any dead-lettering
+        // moves the files. A call the ledger already had was reported when it was delivered.
+        let report = match &outcome {
+            Ok(Delivered::Uploaded(entry)) => Some(Ok(entry)),
+            Ok(Delivered::AlreadyInLedger) => None,
+            Err(e) => delay.is_none().then_some(Err(e)),
+        };
+        if let Some(report) = report {
+            notifier.notify(&job, report).instrument(span.clone()).await;
+        }
+        span.in_scope(|| {
+            let result = match outcome {
+                Ok(_) => queue.ack(job.id),
+                Err(e) => {
+                    health.record_error(&job.sink, &stem, e.to_string(), e.responded());
+                    if let Some(delay) = delay {
+                        warn!(retry_in = ?delay, "Upload failed, will retry: {}", e);
+                        queue.release(job.id, delay, &e.to_string())
+                    } else {
+                        error!("Upload failed permanently: {}", e);
+                        let reason = DeadLetterReason {
+                            mp3_path: &job.mp3_path,
+                            txt_path: &job.txt_path,
+                            sink: &job.sink,
+                            error: e.to_string(),
+                            status: e.status().map(|s| s.as_u16()),
+                            attempts: attempt,
+                            failed_at: dead_letter::unix_now(),
+                        };
+                        // Other sinks may still deliver the call, so leave it where they expect it.
+                        let keep_originals = queue.queued_elsewhere(job.id, &job.mp3_path).unwrap_or(true);
+                        match dead_letter::bury(&config.queue.dead_letter_directory, &reason, keep_originals) {
+                            Ok(sidecar) if keep_originals => {
+                                info!(sidecar = %sidecar.display(), "Copied to dead-letter directory")
+                            }
+                            Ok(sidecar) => info!(sidecar = %sidecar.display(), "Moved to dead-letter directory"),
+                            Err(e) => error!("Failed to dead-letter: {}", e),
+                        }
+                        queue.ack(job.id)
+                    }
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to update upload queue: {}", e);
+            }
+        });
+    }
+}
+
+/// How `process_job` settled a call.
+enum Delivered {
+    Uploaded(LedgerEntry),
+    /// The ledger shows the sink already accepted the same content.
+    AlreadyInLedger,
+}
+
+/// Sends one queued call to `sink` unless the ledger shows the sink already accepted the
+/// same content.
+async fn process_job(
+    sink: &dyn UploadSink,
+    ledger: &Ledger,
+    metrics: &Metrics,
+    health: &Health,
+    config: &Config,
+    job: &Job,
+) -> Result<Delivered, UploadError> {
+    let files = CallFiles::read(&job.mp3_path, &job.txt_path).await.map_err(UploadError::Io)?;
+    let stem = files.stem();
+    let (mp3_sha256, txt_sha256) = (digest::sha256_hex(&files.mp3), digest::sha256_hex(&files.txt));
+    match ledger.find(sink.name(), &stem, &mp3_sha256, &txt_sha256) {
+        Ok(Some(entry)) => {
+            info!(uploaded_at = entry.uploaded_at, call_id = entry.call_id.as_deref(), "Skipping, already uploaded");
+            return Ok(Delivered::AlreadyInLedger);
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to check upload ledger: {}", e),
+    }
+
+    // A call queued under a root that has since been removed from the config gets the defaults.
+    let default_root = RootConfig::default();
+    let root = config.root_for(&job.mp3_path).unwrap_or(&default_root);
+    let filename = job.mp3_path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(record) = root.parse_filename(&filename) else {
+        metrics.parse_failures.inc();
+        return Err(UploadError::UnrecognizedFilename(filename.into_owned()));
+    };
+
+    let started = Instant::now();
+    let result = sink.upload(&Call { record, root, files: &files }).await;
+    match &result {
+        // Rejected before anything was sent.
+        Err(UploadError::InvalidTimestamp(_)) => metrics.parse_failures.inc(),
+        _ => {
+            metrics.upload_duration.with_label_values(&[sink.name()]).observe(started.elapsed().as_secs_f64());
+            if let Ok(receipt) = &result {
+                metrics.bytes_sent.with_label_values(&[sink.name()]).inc_by(receipt.bytes_sent);
+            }
+            let status = result.as_ref().map_or_else(UploadError::status, |receipt| Some(receipt.status));
+            metrics.record_upload(sink.name(), result.is_ok(), status);
+        }
+    }
+    let receipt = result?;
+    info!(status = receipt.status.as_u16(), call_id = receipt.call_id.as_deref(), "Call delivered");
+    health.record_success(sink.name(), &stem, format!("{} {}", receipt.status, receipt.call_id.as_deref().unwrap_or("-")));
+    let entry = LedgerEntry {
+        stem,
+        sink: sink.name().to_string(),
+        mp3_sha256,
+        txt_sha256,
+        mp3_path: job.mp3_path.to_string_lossy().into_owned(),
+        uploaded_at: dead_letter::unix_now() as i64,
+        status: receipt.status.as_u16(),
+        call_id: receipt.call_id,
+        response: receipt.body,
+    };
+    if let Err(e) = ledger.record(&entry) {
+        error!("Failed to record call in upload ledger: {}", e);
+    }
+    Ok(Delivered::Uploaded(entry))
+}
+
+fn extract_file_info(file_path: &Path) -> Option<(PathBuf, PathBuf)> {
     let file_stem = file_path.file_stem()?.to_str()?;
     let parent_dir = file_path.parent()?;
     let mp3_path = parent_dir.join(format!("{}.mp3", file_stem));
@@ -93,24 +700,7 @@ fn extract_file_info(file_path: &PathBuf) -> Option<(PathBuf, PathBuf)> {
     if mp3_path.exists() && txt_path.exists() {
         Some((mp3_path, txt_path))
     } else {
-        println!("Either MP3 or TXT file does not exist");
+        trace!(path = %file_path.display(), "Either MP3 or TXT file does not exist");
         None
     }
 }
-
-fn parse_filename(filename: &str) -> Option<(String, String, String)> {
-    println!("Parsing filename: {}", filename);
-    // This regex is designed to match the timestamp, talkgroup ID, and optionally the radio ID.
-    // It defaults to "123456" if the radio ID is not found.
-    let re = Regex::new(
-        r"(\d{8}_\d{6}).*__TO_(\d+)(?:_FROM_(\d+))?"
-    ).unwrap();
-
-    re.captures(filename).and_then(|cap| {
-        let timestamp = cap.get(1)?.as_str().to_string();
-        let talkgroup_id = cap.get(2)?.as_str().to_string();
-        // Use the captured radio ID if present; otherwise, default to "123456".
-        let radio_id = cap.get(3).map_or("123456".to_string(), |m| m.as_str().to_string());
-        Some((timestamp, talkgroup_id, radio_id))
-    })
-}
@@ -0,0 +1,181 @@
+use crate::{
+    config::WebhookConfig,
+    digest::{hex, hmac_sha256},
+    event::CallEvent,
+    retry::RetryPolicy,
+    sink::{status_error, UploadError},
+};
+use reqwest::{header::CONTENT_TYPE, Client};
+use std::future::Future;
+use tracing::{debug, error, info_span, warn, Instrument};
+
+/// A service told about calls with a JSON POST, as configured in `[[webhooks]]`.
+pub struct Webhook {
+    settings: WebhookConfig,
+    policy: RetryPolicy,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(settings: WebhookConfig, client: Client) -> Self {
+        let policy = settings.retry.policy();
+        Webhook { settings, policy, client }
+    }
+
+    /// Whether a call that `sink` delivered, or failed to, should be reported.
+    pub fn wants(&self, sink: &str, delivered: bool) -> bool {
+        (delivered || self.settings.failures) && (self.settings.sinks.is_empty() || self.settings.sinks.iter().any(|s| s == sink))
+    }
+
+    /// Renders and signs the request for `event`, returning a future that sends it, retrying
+    /// under the webhook's own policy until it is accepted or the policy gives up.
+    pub fn send(&self, event: &CallEvent) -> impl Future<Output = ()> + Send + 'static {
+        let body = match &self.settings.body {
+            Some(body) => body.render_with(&event.fields, json_escape),
+            None => serde_json::to_string(event).expect("call events always serialize"),
+        };
+        let signature = self
+            .settings
+            .secret
+            .as_ref()
+            .map(|secret| format!("sha256={}", hex(&hmac_sha256(secret.as_bytes(), body.as_bytes()))));
+        let (settings, policy, client) = (self.settings.clone(), self.policy, self.client.clone());
+        let span = info_span!("webhook", webhook = %settings.name);
+        async move {
+            for attempt in 1.. {
+                let mut request = client.post(&settings.url).header(CONTENT_TYPE, "application/json").body(body.clone());
+                for (name, value) in &settings.headers {
+                    request = request.header(name, value);
+                }
+                if let Some(signature) = &signature {
+                    request = request.header(&settings.signature_header, signature);
+                }
+                let e = match request.send().await {
+                    Ok(response) if response.status().is_success() => {
+                        debug!(status = response.status().as_u16(), "Webhook delivered");
+                        return;
+                    }
+                    Ok(response) => status_error(response).await,
+                    Err(e) => UploadError::Http(e),
+                };
+                let delay = if e.is_retryable() { policy.delay_for(attempt, e.retry_after()) } else { None };
+                match delay {
+                    Some(delay) => {
+                        warn!(attempt, retry_in = ?delay, "Webhook failed, will retry: {}", e);
+                        tokio::time::sleep(delay).await;
+                    }
+                    None => {
+                        error!(attempt, "Webhook failed permanently: {}", e);
+                        return;
+                    }
+                }
+            }
+        }
+        .instrument(span)
+    }
+}
+
+/// Escapes `value` to sit inside a JSON string.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("strings always serialize");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::tests::mock_server;
+    use std::collections::HashMap;
+
+    fn event(transcript: &str) -> CallEvent {
+        CallEvent {
+            stem: "20240101_120000Metro__TO_52197".to_string(),
+            sink: "api".to_string(),
+            timestamp: "2024-01-01T12:00:00Z".to_string(),
+            system: Some("Metro".to_string()),
+            talkgroup: "52197".to_string(),
+            talkgroup_alias: None,
+            radio_id: None,
+            radio_alias: None,
+            duration_secs: Some(4.5),
+            transcript: transcript.to_string(),
+            result: "delivered",
+            status: Some(200),
+            call_id: Some("42".to_string()),
+            error: None,
+            attempts: 1,
+            fields: HashMap::from([
+                ("talkgroup", "52197".to_string()),
+                ("transcript", transcript.to_string()),
+                ("radio", String::new()),
+            ]),
+        }
+    }
+
+    fn webhook(url: String, body: Option<&str>, secret: Option<&str>) -> Webhook {
+        let settings = WebhookConfig {
+            name: "hook".to_string(),
+            url,
+            body: body.map(|body| body.parse().unwrap()),
+            secret: secret.map(str::to_string),
+            ..WebhookConfig::default()
+        };
+        Webhook::new(settings, Client::new())
+    }
+
+    #[test]
+    fn json_escape_keeps_strings_valid() {
+        for value in ["plain", "say \"hi\"", r"C:\path", "two\nlines\ttab", "bell\u{7}", "café 📻", ""] {
+            let json = format!("\"{}\"", json_escape(value));
+            assert_eq!(serde_json::from_str::<String>(&json).unwrap(), value, "{}", json);
+        }
+        assert_eq!(json_escape("a\"b\\c\n"), r#"a\"b\\c\n"#);
+    }
+
+    #[tokio::test]
+    async fn signs_the_body_it_sends() {
+        let (url, received) = mock_server(&[(200, "")]).await;
+        webhook(url, None, Some("s3cret")).send(&event("Engine 1 \"responding\"")).await;
+
+        let received = received.lock().unwrap();
+        let request = &received[0];
+        assert_eq!(request.headers[CONTENT_TYPE], "application/json");
+        let expected = format!("sha256={}", hex(&hmac_sha256(b"s3cret", &request.body)));
+        assert_eq!(request.headers["X-Signature-256"], expected.as_str());
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["transcript"], "Engine 1 \"responding\"");
+        assert_eq!(body["call_id"], "42");
+    }
+
+    #[tokio::test]
+    async fn unsigned_without_a_secret() {
+        let (url, received) = mock_server(&[(200, "")]).await;
+        webhook(url, None, None).send(&event("")).await;
+        assert!(!received.lock().unwrap()[0].headers.contains_key("X-Signature-256"));
+    }
+
+    #[tokio::test]
+    async fn templated_bodies_escape_values_into_valid_json() {
+        let (url, received) = mock_server(&[(200, "")]).await;
+        let body = r#"{{"text": "{transcript}", "talkgroup": "{talkgroup}", "radio": "{radio}"}}"#;
+        let transcript = "line one\nline \"two\" \\ done";
+        webhook(url, Some(body), Some("s3cret")).send(&event(transcript)).await;
+
+        let received = received.lock().unwrap();
+        let sent: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+        assert_eq!(sent, serde_json::json!({ "text": transcript, "talkgroup": "52197", "radio": "" }));
+        let expected = format!("sha256={}", hex(&hmac_sha256(b"s3cret", &received[0].body)));
+        assert_eq!(received[0].headers["X-Signature-256"], expected.as_str());
+    }
+
+    #[test]
+    fn wants_filters_by_sink_and_outcome() {
+        let all = webhook("http://127.0.0.1".to_string(), None, None);
+        assert!(all.wants("api", true));
+        assert!(!all.wants("api", false));
+        let settings = WebhookConfig { sinks: vec!["api".to_string()], failures: true, ..WebhookConfig::default() };
+        let some = Webhook::new(settings, Client::new());
+        assert!(some.wants("api", false));
+        assert!(!some.wants("mirror", true));
+    }
+}